@@ -1,111 +1,310 @@
 use rayon::prelude::*;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
 use std::io::{self, Read, Write};
+use std::process;
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+pub fn main() -> Result<()> {
+    let mut grammar = BracketGrammar::default();
+    let mut mode = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--lint" | "--fix" => mode = Some(arg),
+            "--grammar" => {
+                let path = args.next().ok_or("expected grammar path")?;
+                grammar = BracketGrammar::parse(&fs::read_to_string(path)?)?;
+            }
+            _ => return Err(format!("unknown flag: {}", arg).into()),
+        }
+    }
 
-fn main() -> Result<()> {
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
 
-    io::stdout().write_fmt(format_args!("Part 1: {}\n", part1(&input)))?;
-    io::stdout().write_fmt(format_args!("Part 2: {}\n", part2(&input)))?;
+    let broken = match mode.as_deref() {
+        Some("--lint") => lint(&input, &grammar, &mut io::stdout())?,
+        Some(_) => fix(&input, &grammar, &mut io::stdout(), &mut io::stderr())?,
+        None => {
+            io::stdout().write_fmt(format_args!("Part 1: {}\n", part1_with(&input, &grammar)))?;
+            io::stdout().write_fmt(format_args!("Part 2: {}\n", part2_with(&input, &grammar)))?;
+            0
+        }
+    };
+    if broken != 0 {
+        process::exit(1);
+    }
 
     Ok(())
 }
 
-pub fn part1(input: &str) -> usize {
-    let mut sum = 0;
-    let mut stack = Vec::<u8>::new();
-
-    'outer: for line in input.lines() {
-        stack.truncate(0);
-        for &c in line.as_bytes() {
-            match c {
-                b'(' | b'[' | b'{' | b'<' => stack.push(c),
-                b')' => {
-                    if !matches!(stack.pop(), Some(b'(')) {
-                        sum += 3;
-                        continue 'outer;
-                    }
-                }
-                b']' => {
-                    if !matches!(stack.pop(), Some(b'[')) {
-                        sum += 57;
-                        continue 'outer;
-                    }
-                }
-                b'}' => {
-                    if !matches!(stack.pop(), Some(b'{')) {
-                        sum += 1197;
-                        continue 'outer;
-                    }
-                }
-                b'>' => {
-                    if !matches!(stack.pop(), Some(b'<')) {
-                        sum += 25137;
-                        continue 'outer;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pair {
+    pub open: char,
+    pub close: char,
+    // Score for a corrupted line with this closing bracket.
+    pub corrupted: usize,
+    // Score for this closing bracket in a completion string.
+    pub completion: usize,
+}
+
+// BracketGrammar defines bracket pairs and scores for corrupted and incomplete lines.
+#[derive(Debug, Clone)]
+pub struct BracketGrammar {
+    pairs: Vec<Pair>,
+    // Completion score is multiplied by base before adding the next bracket.
+    base: usize,
+    // Index of a pair and whether the bracket is opening for every bracket.
+    brackets: HashMap<char, (usize, bool)>,
+}
+
+// Status is the result of checking a single line.
+// Columns are counted in characters starting from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Valid,
+    // Closing bracket of pair `found` was met while `expected` pair was open.
+    Corrupted {
+        column: usize,
+        expected: Option<usize>,
+        found: usize,
+    },
+    // Indices of pairs which are not closed, innermost last.
+    Incomplete(Vec<usize>),
+    Illegal {
+        column: usize,
+        found: char,
+    },
+}
+
+impl Default for BracketGrammar {
+    fn default() -> Self {
+        let pairs = vec![
+            Pair {
+                open: '(',
+                close: ')',
+                corrupted: 3,
+                completion: 1,
+            },
+            Pair {
+                open: '[',
+                close: ']',
+                corrupted: 57,
+                completion: 2,
+            },
+            Pair {
+                open: '{',
+                close: '}',
+                corrupted: 1197,
+                completion: 3,
+            },
+            Pair {
+                open: '<',
+                close: '>',
+                corrupted: 25137,
+                completion: 4,
+            },
+        ];
+        BracketGrammar::new(pairs, 5).unwrap()
+    }
+}
+
+impl BracketGrammar {
+    pub fn new(pairs: Vec<Pair>, base: usize) -> Result<BracketGrammar> {
+        let mut brackets = HashMap::new();
+        for (i, p) in pairs.iter().enumerate() {
+            if p.open == p.close
+                || brackets.insert(p.open, (i, true)).is_some()
+                || brackets.insert(p.close, (i, false)).is_some()
+            {
+                return Err(format!("bracket is used twice in {}{}", p.open, p.close).into());
+            }
+        }
+        Ok(BracketGrammar {
+            pairs,
+            base,
+            brackets,
+        })
+    }
+
+    // parse reads grammar from a config with `<open> <close> <corrupted> <completion>`
+    // on every line and an optional `base <n>` line. Lines starting with `#` are ignored.
+    pub fn parse(config: &str) -> Result<BracketGrammar> {
+        let mut pairs = Vec::new();
+        let mut base = 5;
+        for line in config.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                ["base", n] => base = n.parse()?,
+                [open, close, corrupted, completion] => pairs.push(Pair {
+                    open: single_char(open)?,
+                    close: single_char(close)?,
+                    corrupted: corrupted.parse()?,
+                    completion: completion.parse()?,
+                }),
+                _ => return Err(format!("invalid grammar line: {}", line).into()),
+            }
+        }
+        BracketGrammar::new(pairs, base)
+    }
+
+    pub fn check(&self, line: &str) -> Status {
+        let mut stack = Vec::new();
+        for (column, c) in (1..).zip(line.chars()) {
+            match self.brackets.get(&c) {
+                Some(&(i, true)) => stack.push(i),
+                Some(&(i, false)) => {
+                    let expected = stack.pop();
+                    if expected != Some(i) {
+                        return Status::Corrupted {
+                            column,
+                            expected,
+                            found: i,
+                        };
                     }
                 }
-                _ => unreachable!("unexpected input"),
+                None => return Status::Illegal { column, found: c },
+            }
+        }
+        if stack.is_empty() {
+            Status::Valid
+        } else {
+            Status::Incomplete(stack)
+        }
+    }
+
+    // completion returns closing brackets for all pairs on the stack.
+    pub fn completion(&self, stack: &[usize]) -> String {
+        stack.iter().rev().map(|&i| self.pairs[i].close).collect()
+    }
+
+    // describe returns a human-readable diagnostic for the line number n.
+    pub fn describe(&self, n: usize, status: &Status) -> String {
+        match status {
+            Status::Valid => format!("{}: valid", n),
+            Status::Corrupted {
+                column,
+                expected: Some(e),
+                found,
+            } => format!(
+                "{}:{}: corrupted: expected '{}', found '{}'",
+                n, column, self.pairs[*e].close, self.pairs[*found].close
+            ),
+            Status::Corrupted {
+                column,
+                expected: None,
+                found,
+            } => format!(
+                "{}:{}: corrupted: nothing to close, found '{}'",
+                n, column, self.pairs[*found].close
+            ),
+            Status::Incomplete(stack) => {
+                format!("{}: incomplete: missing '{}'", n, self.completion(stack))
+            }
+            Status::Illegal { column, found } => {
+                format!("{}:{}: illegal character '{}'", n, column, found)
             }
         }
     }
 
-    sum
+    pub fn completion_score(&self, stack: &[usize]) -> usize {
+        stack
+            .iter()
+            .rev()
+            .fold(0, |acc, &i| acc * self.base + self.pairs[i].completion)
+    }
+}
+
+fn single_char(s: &str) -> Result<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(format!("expected single character: {}", s).into()),
+    }
+}
+
+// lint writes a diagnostic for every line which is not valid
+// and returns the amount of such lines.
+pub fn lint(input: &str, grammar: &BracketGrammar, out: &mut impl Write) -> io::Result<usize> {
+    let mut count = 0;
+    for (n, line) in (1..).zip(input.lines()) {
+        let status = grammar.check(line);
+        if status != Status::Valid {
+            count += 1;
+            writeln!(out, "{}", grammar.describe(n, &status))?;
+        }
+    }
+    Ok(count)
+}
+
+// fix writes every line to out, completing incomplete lines.
+// Corrupted lines and lines with illegal characters are written as is
+// and reported to err. It returns the amount of reported lines.
+pub fn fix(
+    input: &str,
+    grammar: &BracketGrammar,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> io::Result<usize> {
+    let mut count = 0;
+    for (n, line) in (1..).zip(input.lines()) {
+        let status = grammar.check(line);
+        match status {
+            Status::Valid => writeln!(out, "{}", line)?,
+            Status::Incomplete(ref stack) => {
+                writeln!(out, "{}{}", line, grammar.completion(stack))?
+            }
+            _ => {
+                count += 1;
+                writeln!(out, "{}", line)?;
+                writeln!(err, "{}", grammar.describe(n, &status))?;
+            }
+        }
+    }
+    Ok(count)
+}
+
+pub fn part1(input: &str) -> usize {
+    part1_with(input, &BracketGrammar::default())
+}
+
+pub fn part1_with(input: &str, grammar: &BracketGrammar) -> usize {
+    input
+        .lines()
+        .map(|line| match grammar.check(line) {
+            Status::Corrupted { found, .. } => grammar.pairs[found].corrupted,
+            _ => 0,
+        })
+        .sum()
 }
 
 pub fn part2(input: &str) -> usize {
+    part2_with(input, &BracketGrammar::default())
+}
+
+pub fn part2_with(input: &str, grammar: &BracketGrammar) -> usize {
     let mut scores: Vec<usize> = input
         .par_lines()
-        .filter_map(|line| {
-            let mut stack = Vec::<u8>::new();
-
-            for &c in line.as_bytes() {
-                match c {
-                    b'(' | b'[' | b'{' | b'<' => stack.push(c),
-                    b')' => {
-                        if !matches!(stack.pop(), Some(b'(')) {
-                            return None;
-                        }
-                    }
-                    b']' => {
-                        if !matches!(stack.pop(), Some(b'[')) {
-                            return None;
-                        }
-                    }
-                    b'}' => {
-                        if !matches!(stack.pop(), Some(b'{')) {
-                            return None;
-                        }
-                    }
-                    b'>' => {
-                        if !matches!(stack.pop(), Some(b'<')) {
-                            return None;
-                        }
-                    }
-                    _ => unreachable!("unexpected input"),
-                }
-            }
-            Some(stack.iter().rev().fold(0_usize, |acc, &c| {
-                acc * 5
-                    + match c {
-                        b'(' => 1,
-                        b'[' => 2,
-                        b'{' => 3,
-                        b'<' => 4,
-                        _ => unreachable!("BUG: unexpected item on stack"),
-                    }
-            }))
+        .filter_map(|line| match grammar.check(line) {
+            Status::Incomplete(stack) => Some(grammar.completion_score(&stack)),
+            _ => None,
         })
         .collect();
 
     scores.sort_unstable();
-    scores[scores.len() / 2]
+    scores.get(scores.len() / 2).copied().unwrap_or(0)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{part1, part2};
+    use crate::{fix, lint, part1, part1_with, part2, part2_with, BracketGrammar, Status};
 
     #[test]
     fn example() {
@@ -120,7 +319,67 @@ mod tests {
 <{([([[(<>()){}]>(<<{{
 <{([{{}}[<[[[<>{}]]]>[]]";
 
-        assert_eq!(26397, part1(&input));
-        assert_eq!(288957, part2(&input));
+        assert_eq!(26397, part1(input));
+        assert_eq!(288957, part2(input));
+    }
+
+    #[test]
+    fn grammar() {
+        let grammar = BracketGrammar::parse(
+            "# LaTeX-like delimiters
+            ( ) 1 1
+            « » 10 2
+            base 3",
+        )
+        .unwrap();
+
+        let input = "(«)\n((«\n«()»\n(«(\n(»";
+        assert_eq!(1 + 10, part1_with(input, &grammar));
+        // Completions are `»))` = (2*3 + 1)*3 + 1 = 22 and `)»)` = (1*3 + 2)*3 + 1 = 16.
+        assert_eq!(22, part2_with(input, &grammar));
+
+        assert!(BracketGrammar::parse("( ) 1 1\n[ ( 1 1").is_err());
+        assert!(BracketGrammar::parse("( ) 1").is_err());
+    }
+
+    #[test]
+    fn linter() {
+        let grammar = BracketGrammar::default();
+        let input = "{([(<{}[<>[]}>{[]{[(<()>\n[({(<(())[]>[[{[]{<()<>>\n<>()\n(x)\n)";
+
+        assert_eq!(
+            Status::Corrupted {
+                column: 13,
+                expected: Some(1),
+                found: 2
+            },
+            grammar.check("{([(<{}[<>[]}>{[]{[(<()>")
+        );
+        assert_eq!(
+            Status::Illegal {
+                column: 2,
+                found: 'x'
+            },
+            grammar.check("(x)")
+        );
+
+        let mut out = Vec::new();
+        assert_eq!(4, lint(input, &grammar, &mut out).unwrap());
+        assert_eq!(
+            "1:13: corrupted: expected ']', found '}'
+2: incomplete: missing '}}]])})]'
+4:2: illegal character 'x'
+5:1: corrupted: nothing to close, found ')'
+",
+            String::from_utf8(out).unwrap()
+        );
+
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        assert_eq!(3, fix(input, &grammar, &mut out, &mut err).unwrap());
+        assert_eq!(
+            "{([(<{}[<>[]}>{[]{[(<()>\n[({(<(())[]>[[{[]{<()<>>}}]])})]\n<>()\n(x)\n)\n",
+            String::from_utf8(out).unwrap()
+        );
+        assert_eq!(3, String::from_utf8(err).unwrap().lines().count());
     }
 }
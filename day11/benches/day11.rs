@@ -3,12 +3,14 @@ use day11::{part1, part2};
 
 pub fn part1_bench(c: &mut Criterion) {
     let input = include_str!("../input");
-    c.bench_function("part 1", |b| b.iter(|| part1(black_box(input), 100)));
+    c.bench_function("part 1", |b| {
+        b.iter(|| part1(black_box(input), 100).unwrap())
+    });
 }
 
 pub fn part2_bench(c: &mut Criterion) {
     let input = include_str!("../input");
-    c.bench_function("part 2", |b| b.iter(|| part2(black_box(input))));
+    c.bench_function("part 2", |b| b.iter(|| part2(black_box(input)).unwrap()));
 }
 
 criterion_group!(benches, part1_bench, part2_bench);
@@ -1,141 +1,448 @@
+use std::collections::HashMap;
+use std::env;
 use std::io::{self, Read, Write};
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 pub fn main() -> Result<()> {
+    let mut config = Config::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--wrap" => config.wrap = true,
+            "--threshold" => config.threshold = args.next().ok_or("expected threshold")?.parse()?,
+            _ => return Err(format!("unknown flag: {}", arg).into()),
+        }
+    }
+
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
 
-    io::stdout().write_fmt(format_args!("Part 1: {}\n", part1(&input, 100)))?;
-    io::stdout().write_fmt(format_args!("Part 2: {}\n", part2(&input)))?;
+    io::stdout().write_fmt(format_args!(
+        "Part 1: {}\n",
+        part1_with(&input, 100, &config)?
+    ))?;
+    io::stdout().write_fmt(format_args!("Part 2: {}\n", part2_with(&input, &config)?))?;
 
     Ok(())
 }
 
-fn parse(input: &str) -> (usize, Vec<u8>) {
-    let res: Vec<u8> = input
-        .lines()
-        .flat_map(|line| line.as_bytes().iter().map(|&c| c - b'0'))
-        .collect();
-    ((res.len() as f64).sqrt() as usize, res)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    // Octopus flashes when its energy reaches threshold.
+    pub threshold: u32,
+    // Whether opposite edges of the grid are adjacent.
+    pub wrap: bool,
 }
 
-// VALUE_MASK is a mask for retrieving an actual charge of an octopus.
-const VALUE_MASK: u8 = 0x3F;
-// FLASH_BIT is 1 if a cell was already flashed.
-const FLASH_BIT: u8 = 7;
-// USED_BIT is 1 if a cell has been altered by the flash of neighbour.
-const USED_BIT: u8 = 6;
-// USED_MASK is a mask for checking if cell has been altered.
-const USED_MASK: u8 = 1 << USED_BIT;
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            threshold: 10,
+            wrap: false,
+        }
+    }
+}
+
+// Grid never touches octopuses which don't flash: every octopus stores the step
+// at which it reaches threshold if none of its neighbours flash before that.
+// Octopuses are grouped by this step, so only those which can flash are visited.
+#[derive(Debug, Clone)]
+pub struct Grid {
+    width: usize,
+    height: usize,
+    config: Config,
+    // Steps made so far.
+    time: i64,
+    // Step at which each octopus reaches threshold on its own.
+    due: Vec<i64>,
+    // Octopuses which are expected to flash at the given step.
+    // Entries become stale when a neighbour flash moves the due step.
+    pending: HashMap<i64, Vec<usize>>,
+    flashed: Vec<bool>,
+    // Sum of cell weights, see weight().
+    hash: u64,
+    weights: Vec<u64>,
+}
+
+// Hashes are computed modulo a Mersenne prime.
+const MODULUS: u64 = (1 << 61) - 1;
+const BASE: u64 = 3;
+
+fn mul_mod(a: u64, b: u64) -> u64 {
+    (a as u128 * b as u128 % MODULUS as u128) as u64
+}
 
-fn mark(i: usize, field: &mut [u8]) {
-    if i < field.len() {
-        field[i] += 1;
-        field[i] |= USED_MASK;
+fn pow_mod(mut base: u64, exp: i64) -> u64 {
+    let mut exp = exp.rem_euclid(MODULUS as i64 - 1) as u64;
+    let mut result = 1;
+    while exp != 0 {
+        if exp & 1 != 0 {
+            result = mul_mod(result, base);
+        }
+        base = mul_mod(base, base);
+        exp >>= 1;
     }
+    result
+}
+
+// salt returns a pseudo-random multiplier for the cell i.
+fn salt(i: usize) -> u64 {
+    let mut z = (i as u64)
+        .wrapping_add(1)
+        .wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31)) % (MODULUS - 1) + 1
 }
 
-fn step(width: usize, field: &mut [u8]) -> usize {
-    assert!(width > 0);
-    assert!(field.len() == width * width);
+impl Grid {
+    pub fn parse(input: &str, config: &Config) -> Result<Grid> {
+        let mut width = 0;
+        let mut cells = Vec::new();
+        for line in input.lines().map(|line| line.trim().as_bytes()) {
+            if width == 0 {
+                width = line.len();
+            } else if width != line.len() {
+                return Err("rows have different lengths".into());
+            }
+            if !line.iter().all(u8::is_ascii_digit) {
+                return Err("expected digits".into());
+            }
+            cells.extend(line.iter().map(|&c| (c - b'0') as i64));
+        }
+        if config.threshold == 0 {
+            return Err("threshold must be positive".into());
+        }
+
+        let threshold = config.threshold as i64;
+        let due: Vec<i64> = cells.iter().map(|&energy| threshold - energy).collect();
+        let mut pending: HashMap<i64, Vec<usize>> = HashMap::new();
+        for (i, &d) in due.iter().enumerate() {
+            pending.entry(d.max(1)).or_default().push(i);
+        }
+        let weights: Vec<u64> = due
+            .iter()
+            .enumerate()
+            .map(|(i, &d)| mul_mod(salt(i), pow_mod(BASE, d)))
+            .collect();
 
-    for cell in field.iter_mut() {
-        *cell = ((((*cell >> FLASH_BIT == 0) as u8) * *cell) & VALUE_MASK) + 1
+        Ok(Grid {
+            width,
+            height: cells.len().checked_div(width).unwrap_or(0),
+            config: *config,
+            time: 0,
+            pending,
+            flashed: vec![false; due.len()],
+            hash: weights.iter().fold(0, |acc, &w| (acc + w) % MODULUS),
+            weights,
+            due,
+        })
     }
 
-    let mut count = 0;
-    let mut iter = 0;
-    loop {
-        let old_count = count;
-        for i in 0..field.len() {
-            let overflow =
-                field[i] & VALUE_MASK >= 10 && (iter == 0 || (field[i] & USED_MASK) != 0);
-            let high_bit = field[i] & (1 << FLASH_BIT);
-            if high_bit == 0 && overflow {
-                field[i] = 1 << FLASH_BIT;
-                count += 1;
-
-                // Previous row.
-                if width <= i {
-                    if i % width != 0 {
-                        mark(i - width - 1, field);
-                    }
-                    mark(i - width, field);
-                    if i % width != width - 1 {
-                        mark(i - width + 1, field);
-                    }
-                }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.due.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.due.is_empty()
+    }
+
+    // energy returns current energy levels of all octopuses.
+    pub fn energy(&self) -> Vec<u32> {
+        let threshold = self.config.threshold as i64;
+        self.due
+            .iter()
+            .map(|&d| (threshold + self.time - d) as u32)
+            .collect()
+    }
 
-                // Current row.
-                if i % width != 0 {
-                    mark(i - 1, field);
+    // neighbours returns indices of cells adjacent to i and their amount.
+    fn neighbours(&self, i: usize) -> ([usize; 8], usize) {
+        let (w, h) = (self.width as isize, self.height as isize);
+        let (x, y) = ((i % self.width) as isize, (i / self.width) as isize);
+        let mut result = [0; 8];
+        let mut n = 0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (mut nx, mut ny) = (x + dx, y + dy);
+                if self.config.wrap {
+                    nx = nx.rem_euclid(w);
+                    ny = ny.rem_euclid(h);
+                } else if nx < 0 || ny < 0 || nx >= w || ny >= h {
+                    continue;
                 }
-                if i % width != width - 1 {
-                    mark(i + 1, field);
+
+                // On narrow toroidal grids different offsets can point to the same cell.
+                let j = (ny * w + nx) as usize;
+                if j != i && !result[..n].contains(&j) {
+                    result[n] = j;
+                    n += 1;
                 }
+            }
+        }
+        (result, n)
+    }
 
-                // Next row.
-                if i + width < field.len() {
-                    if i % width != 0 {
-                        mark(i + width - 1, field);
-                    }
-                    mark(i + width, field);
-                    if i % width != width - 1 {
-                        mark(i + width + 1, field);
-                    }
+    // set_weight replaces the weight of the cell i keeping the hash sum up to date.
+    fn set_weight(&mut self, i: usize, w: u64) {
+        self.hash = (self.hash + MODULUS - self.weights[i] + w) % MODULUS;
+        self.weights[i] = w;
+    }
+
+    // step makes a single step and returns the amount of flashes.
+    pub fn step(&mut self) -> usize {
+        self.time += 1;
+        let now = self.time;
+        let threshold = self.config.threshold as i64;
+        let inverse = pow_mod(BASE, -1);
+
+        let mut queue = self.pending.remove(&now).unwrap_or_default();
+        let mut flashes = Vec::new();
+        while let Some(i) = queue.pop() {
+            if self.flashed[i] || self.due[i] > now {
+                continue;
+            }
+            self.flashed[i] = true;
+            flashes.push(i);
+
+            let (neighbours, n) = self.neighbours(i);
+            for &j in neighbours[..n].iter() {
+                if self.flashed[j] {
+                    continue;
+                }
+                self.due[j] -= 1;
+                self.set_weight(j, mul_mod(self.weights[j], inverse));
+                if self.due[j] <= now {
+                    queue.push(j);
+                } else {
+                    self.pending.entry(self.due[j]).or_default().push(j);
                 }
             }
         }
 
-        if old_count == count {
-            break;
+        let next = now + threshold;
+        let power = pow_mod(BASE, next);
+        for &i in flashes.iter() {
+            self.flashed[i] = false;
+            self.due[i] = next;
+            self.set_weight(i, mul_mod(salt(i), power));
         }
-        iter += 1;
+        if !flashes.is_empty() {
+            self.pending.entry(next).or_default().extend(&flashes);
+        }
+        flashes.len()
     }
-    count
 }
 
-pub fn part1(input: &str, steps: usize) -> usize {
-    let (width, mut field) = parse(input);
+// Simulation is the history of a grid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Simulation {
+    // Amount of flashes for every step starting from step 1.
+    pub flashes: Vec<usize>,
+    // The first step when all octopuses flashed.
+    pub synchronized: Option<usize>,
+    // The first step of a repeating state sequence and its period.
+    pub cycle: Option<(usize, usize)>,
+}
+
+impl Grid {
+    // state_hash is a hash of energy levels. Energy of the cell i is determined
+    // by due[i] - time, so the sum of salt(i) * BASE^due[i] is shifted by BASE^-time.
+    // Only cells which change are rehashed, making the hash cheap to maintain.
+    fn state_hash(&self) -> u64 {
+        mul_mod(self.hash, pow_mod(BASE, -self.time))
+    }
+
+    // simulate makes steps until all octopuses flash simultaneously,
+    // the grid returns to one of its previous states or max_steps are made.
+    pub fn simulate(&mut self, max_steps: usize) -> Simulation {
+        let mut seen = HashMap::new();
+        seen.insert(self.state_hash(), 0);
+
+        let mut sim = Simulation {
+            flashes: Vec::new(),
+            synchronized: None,
+            cycle: None,
+        };
+        for i in 1..=max_steps {
+            let count = self.step();
+            sim.flashes.push(count);
+            if count == self.len() {
+                sim.synchronized = Some(i);
+                break;
+            }
+            if let Some(prev) = seen.insert(self.state_hash(), i) {
+                sim.cycle = Some((prev, i - prev));
+                break;
+            }
+        }
+        sim
+    }
+}
+
+pub fn part1(input: &str, steps: usize) -> Result<usize> {
+    part1_with(input, steps, &Config::default())
+}
+
+pub fn part1_with(input: &str, steps: usize, config: &Config) -> Result<usize> {
+    let mut grid = Grid::parse(input, config)?;
     let mut count = 0;
 
     for _ in 0..steps {
-        count += step(width, &mut field);
+        count += grid.step();
     }
-    count
+    Ok(count)
 }
 
-pub fn part2(input: &str) -> usize {
-    let (width, mut field) = parse(input);
-    let mut count = 1;
+pub fn part2(input: &str) -> Result<usize> {
+    part2_with(input, &Config::default())
+}
 
-    while step(width, &mut field) != width * width {
-        count += 1;
+pub fn part2_with(input: &str, config: &Config) -> Result<usize> {
+    let mut grid = Grid::parse(input, config)?;
+    let sim = grid.simulate(usize::MAX);
+    match (sim.synchronized, sim.cycle) {
+        (Some(step), _) => Ok(step),
+        (None, Some((start, period))) => Err(format!(
+            "grid never synchronises: cycle of {} steps starts at step {}",
+            period, start
+        )
+        .into()),
+        _ => Err("grid never synchronises".into()),
     }
-    count
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{part1, part2};
+    use crate::{part1, part1_with, part2, part2_with, Config, Grid, Simulation};
 
     #[test]
     fn example() {
         let input = "000\n090\n000";
-        assert_eq!(1, part1(&input, 1));
-        assert_eq!(1, part1(&input, 2));
-        assert_eq!(1, part1(&input, 7));
-        assert_eq!(1, part1(&input, 8));
-        assert_eq!(10, part1(&input, 9));
+        assert_eq!(1, part1(input, 1).unwrap());
+        assert_eq!(1, part1(input, 2).unwrap());
+        assert_eq!(1, part1(input, 7).unwrap());
+        assert_eq!(1, part1(input, 8).unwrap());
+        assert_eq!(10, part1(input, 9).unwrap());
 
         let input = "5483143223\n2745854711\n5264556173\n6141336146\n6357385478\n4167524645\n2176841721\n6882881134\n4846848554\n5283751526";
 
-        assert_eq!(0, part1(&input, 1));
-        assert_eq!(35, part1(&input, 2));
-        assert_eq!(35 + 45, part1(&input, 3));
-        assert_eq!(1656, part1(&input, 100));
+        assert_eq!(0, part1(input, 1).unwrap());
+        assert_eq!(35, part1(input, 2).unwrap());
+        assert_eq!(35 + 45, part1(input, 3).unwrap());
+        assert_eq!(1656, part1(input, 100).unwrap());
+
+        assert_eq!(195, part2(input).unwrap());
+    }
+
+    #[test]
+    fn rectangular() {
+        let input = "0000\n0900";
+        assert_eq!(1, part1(input, 1).unwrap());
+        assert_eq!(1, part1(input, 2).unwrap());
+        assert!(Grid::parse("000\n00", &Config::default()).is_err());
 
-        assert_eq!(195, part2(&input));
+        // With wrapping the first and the last cells of a row are adjacent.
+        let config = Config {
+            threshold: 10,
+            wrap: true,
+        };
+        assert_eq!(1, part1("9008", 1).unwrap());
+        assert_eq!(2, part1_with("9008", 1, &config).unwrap());
+        assert_eq!(1, part2_with("99\n99", &config).unwrap());
+
+        let config = Config {
+            threshold: 100,
+            wrap: false,
+        };
+        assert_eq!(0, part1_with(input, 90, &config).unwrap());
+        assert_eq!(1, part1_with(input, 91, &config).unwrap());
+    }
+
+    #[test]
+    fn history() {
+        let input = "5483143223\n2745854711\n5264556173\n6141336146\n6357385478\n4167524645\n2176841721\n6882881134\n4846848554\n5283751526";
+        let mut grid = Grid::parse(input, &Config::default()).unwrap();
+        let sim = grid.simulate(usize::MAX);
+        assert_eq!(Some(195), sim.synchronized);
+        assert_eq!(None, sim.cycle);
+        assert_eq!(195, sim.flashes.len());
+        assert_eq!(1656, sim.flashes[..100].iter().sum::<usize>());
+        assert_eq!(100, sim.flashes[194]);
+
+        // Octopuses on this grid never flash together and the states repeat.
+        let mut grid = Grid::parse("059", &Config::default()).unwrap();
+        let sim = grid.simulate(1000);
+        assert_eq!(None, sim.synchronized);
+        assert!(sim.cycle.is_some());
+        assert!(part2("059").is_err());
+
+        let mut grid = Grid::parse("059", &Config::default()).unwrap();
+        assert_eq!(
+            Simulation {
+                flashes: vec![1, 0],
+                synchronized: None,
+                cycle: None
+            },
+            grid.simulate(2)
+        );
+    }
+
+    // naive_step rescans the whole grid until no new flashes occur.
+    fn naive_step(cells: &mut [u32], width: usize, config: &Config) -> usize {
+        let height = cells.len() / width;
+        let mut flashed = vec![false; cells.len()];
+        cells.iter_mut().for_each(|c| *c += 1);
+        loop {
+            let mut changed = false;
+            for i in 0..cells.len() {
+                if flashed[i] || cells[i] < config.threshold {
+                    continue;
+                }
+                flashed[i] = true;
+                changed = true;
+                let mut seen = Vec::new();
+                for (dx, dy) in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy))) {
+                    let (mut x, mut y) = ((i % width) as isize + dx, (i / width) as isize + dy);
+                    if config.wrap {
+                        x = x.rem_euclid(width as isize);
+                        y = y.rem_euclid(height as isize);
+                    } else if x < 0 || y < 0 || x >= width as isize || y >= height as isize {
+                        continue;
+                    }
+                    let j = y as usize * width + x as usize;
+                    if j != i && !seen.contains(&j) {
+                        seen.push(j);
+                        cells[j] += 1;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        cells
+            .iter_mut()
+            .zip(flashed.iter())
+            .filter(|(_, &f)| f)
+            .for_each(|(c, _)| *c = 0);
+        flashed.iter().filter(|&&f| f).count()
+    }
+
+    #[test]
+    fn worklist() {
+        let input = "5483143223\n2745854711\n5264556173\n6141336146\n6357385478\n4167524645\n2176841721\n6882881134\n4846848554\n5283751526";
+        for &(threshold, wrap) in &[(10, false), (10, true), (4, false), (13, true)] {
+            let config = Config { threshold, wrap };
+            let mut grid = Grid::parse(input, &config).unwrap();
+            let mut cells = grid.energy();
+            for _ in 0..300 {
+                assert_eq!(naive_step(&mut cells, 10, &config), grid.step());
+                assert_eq!(cells, grid.energy());
+            }
+        }
     }
 }
@@ -1,14 +1,70 @@
 use std::collections::HashMap;
+use std::env;
+use std::hash::{BuildHasherDefault, Hasher};
 use std::io::{self, Read, Write};
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 pub fn main() -> Result<()> {
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
 
-    io::stdout().write_fmt(format_args!("Part 1: {}\n", part1(&input)))?;
-    io::stdout().write_fmt(format_args!("Part 2: {}\n", part2(&input)))?;
+    let (mut paths, mut json, mut dot) = (false, false, false);
+    let mut highlight = String::new();
+    let mut filter = PathFilter::default();
+    let mut rules = Rules::default();
+    let mut budget = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--paths" => paths = true,
+            "--json" => json = true,
+            "--dot" => dot = true,
+            "--highlight" => highlight = args.next().ok_or("expected path")?,
+            "--twice" => budget = Some(1),
+            "--budget" => budget = Some(args.next().ok_or("expected budget")?.parse()?),
+            "--start" => rules.start = args.next().ok_or("expected cave")?,
+            "--end" => rules.end = args.next().ok_or("expected cave")?,
+            "--limit" => {
+                let limit = args.next().ok_or("expected limit")?;
+                let (name, n) = limit.split_once('=').ok_or("expected <cave>=<visits>")?;
+                rules.limits.insert(name.to_string(), n.parse()?);
+            }
+            "--visit" => filter.must_visit.push(args.next().ok_or("expected cave")?),
+            "--avoid" => filter.avoid.push(args.next().ok_or("expected cave")?),
+            "--max-len" => filter.max_len = Some(args.next().ok_or("expected length")?.parse()?),
+            _ => return Err(format!("unknown flag: {}", arg).into()),
+        }
+    }
+
+    if dot {
+        let caves = parse_with(&input, &rules)?;
+        let path: Vec<&str> = highlight.split(',').filter(|c| !c.is_empty()).collect();
+        caves.write_dot(&mut io::stdout(), &path)?;
+        return Ok(());
+    }
+
+    if paths {
+        let caves = parse_with(&input, &rules)?;
+        let paths = caves.paths(budget.unwrap_or(0), &filter);
+        let mut out = io::stdout();
+        if json {
+            write_json(&mut out, paths)?;
+        } else {
+            write_text(&mut out, paths)?;
+        }
+        return Ok(());
+    }
+
+    if let Some(budget) = budget {
+        rules.budget = budget;
+        io::stdout().write_fmt(format_args!("Paths: {}\n", part_with(&input, &rules)?))?;
+        return Ok(());
+    }
+
+    io::stdout().write_fmt(format_args!("Part 1: {}\n", part_with(&input, &rules)?))?;
+    rules.budget = 1;
+    io::stdout().write_fmt(format_args!("Part 2: {}\n", part_with(&input, &rules)?))?;
 
     Ok(())
 }
@@ -17,84 +73,686 @@ type Graph = Vec<Vec<usize>>;
 
 const START_VERTICE: usize = 0;
 const END_VERTICE: usize = 1;
-// UPPER_BIT is 1 if vertice name is in uppercase.
+// UPPER_BIT is 1 if vertice is a big cave.
 const UPPER_BIT: u32 = usize::BITS - 1;
 // VALUE_MASK is a mask to retrieve real vertex index.
 const VALUE_MASK: usize = (1 << UPPER_BIT) - 1;
 
-fn parse(input: &str) -> Graph {
+// Rules describe how caves can be visited.
+pub struct Rules {
+    pub start: String,
+    pub end: String,
+    // Amount of additional visits of small caves allowed on a single path.
+    pub budget: usize,
+    // Maximum amount of visits of particular small caves.
+    pub limits: HashMap<String, usize>,
+    // is_big returns true for caves which can be visited any amount of times.
+    pub is_big: Box<dyn Fn(&str) -> bool>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            start: "start".to_string(),
+            end: "end".to_string(),
+            budget: 0,
+            limits: HashMap::new(),
+            is_big: Box::new(|name| name.chars().any(char::is_uppercase)),
+        }
+    }
+}
+
+// Caves is a cave system together with the names of its caves.
+pub struct Caves {
+    names: Vec<String>,
+    graph: Graph,
+    big: Vec<bool>,
+    // Maximum amount of visits of every cave, usize::MAX if unrestricted.
+    limits: Vec<usize>,
+}
+
+pub fn parse(input: &str) -> Result<Caves> {
+    parse_with(input, &Rules::default())
+}
+
+pub fn parse_with(input: &str, rules: &Rules) -> Result<Caves> {
+    if rules.start == rules.end {
+        return Err("start and end must be different caves".into());
+    }
+    if (rules.is_big)(&rules.start) || (rules.is_big)(&rules.end) {
+        return Err("start and end must be small caves".into());
+    }
+
     let mut vertices = HashMap::<&str, usize>::new();
-    vertices.insert("start", START_VERTICE);
-    vertices.insert("end", END_VERTICE);
+    vertices.insert(&rules.start, START_VERTICE);
+    vertices.insert(&rules.end, END_VERTICE);
 
     let mut graph = vec![vec![], vec![]];
-    input
-        .lines()
-        .map(|line| line.split_once('-').unwrap())
-        .for_each(|(a_name, b_name)| {
-            let &mut a = vertices.entry(a_name).or_insert_with(|| {
+    let mut names = vec![rules.start.clone(), rules.end.clone()];
+    for line in input.lines() {
+        let (a_name, b_name) = line
+            .split_once('-')
+            .ok_or_else(|| format!("invalid edge: {}", line))?;
+        let mut vertex = |name| {
+            *vertices.entry(name).or_insert_with(|| {
                 graph.push(Vec::with_capacity(1));
+                names.push(name.to_string());
                 graph.len() - 1
-            });
-            let &mut b = vertices.entry(b_name).or_insert_with(|| {
-                graph.push(Vec::with_capacity(1));
-                graph.len() - 1
-            });
-            graph[a].push(b | ((b_name.chars().any(char::is_uppercase) as usize) << UPPER_BIT));
-            graph[b].push(a | ((a_name.chars().any(char::is_uppercase) as usize) << UPPER_BIT));
-        });
-    graph
+            })
+        };
+        let (a, b) = (vertex(a_name), vertex(b_name));
+        graph[a].push(b | (((rules.is_big)(b_name) as usize) << UPPER_BIT));
+        graph[b].push(a | (((rules.is_big)(a_name) as usize) << UPPER_BIT));
+    }
+
+    let big: Vec<bool> = names.iter().map(|name| (rules.is_big)(name)).collect();
+    for name in rules.limits.keys() {
+        // Paths through big caves are contracted, so their visits can't be limited.
+        if (rules.is_big)(name) {
+            return Err(format!("can't limit visits of big cave {}", name).into());
+        }
+        if *name == rules.start || *name == rules.end {
+            return Err(format!("can't limit visits of {} cave", name).into());
+        }
+    }
+    let limits = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| match i {
+            START_VERTICE | END_VERTICE => 1,
+            _ if big[i] => usize::MAX,
+            _ => rules.limits.get(name).copied().unwrap_or(usize::MAX),
+        })
+        .collect();
+    Ok(Caves {
+        names,
+        graph,
+        big,
+        limits,
+    })
+}
+
+// Counter counts paths between start and end. Big caves are contracted:
+// a path through a big cave becomes an edge between two small caves,
+// so the graph only has small caves and edges with multiplicities.
+struct Counter {
+    // Edges between small caves with the amount of ways to go along them.
+    edges: Vec<Vec<(usize, u128)>>,
+    limits: Vec<usize>,
+    // Caves whose limit can be reached before the budget is spent
+    // need their visits to be counted, this is the offset of their
+    // counter in the packed revisits.
+    tracked: Vec<Option<u32>>,
+    // Mask of a single counter in the packed revisits.
+    counter_mask: u64,
+    memo: HashMap<(usize, u64, usize, u64), u128, BuildHasherDefault<MemoHasher>>,
+}
+
+// MemoHasher is a multiplicative hasher for memo keys. Keys are
+// small integers which are not chosen by an adversary, so SipHash is not needed.
+#[derive(Default)]
+struct MemoHasher(u64);
+
+impl Hasher for MemoHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.write_u64(b as u64);
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 = (self.0.rotate_left(5) ^ n).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
 }
 
-fn dfs(v: usize, g: &[Vec<usize>], can_visit_small: bool, visited: &mut [bool]) -> usize {
-    let is_lower = v & (1 << UPPER_BIT) == 0;
-    visited[v & VALUE_MASK] = is_lower;
+impl Counter {
+    fn new(caves: &Caves, budget: usize) -> Result<Counter> {
+        // Small caves are renumbered so that the visited set fits in a bitmask.
+        let mut small = vec![usize::MAX; caves.len()];
+        let mut count = 0;
+        for v in (0..caves.len()).filter(|&v| !caves.is_big(v)) {
+            small[v] = count;
+            count += 1;
+        }
+        if count > u64::BITS as usize {
+            return Err(format!("too many small caves: {}", count).into());
+        }
+
+        // A tracked cave is revisited at most budget times, so its counter
+        // takes as many bits as budget does.
+        let width = usize::BITS - budget.leading_zeros();
+        let mut limits = vec![0; count];
+        let mut tracked = vec![None; count];
+        let mut offset = 0;
+        for v in (0..caves.len()).filter(|&v| !caves.is_big(v)) {
+            limits[small[v]] = caves.limits[v];
+            if (2..=budget).contains(&caves.limits[v]) {
+                if offset + width > u64::BITS {
+                    return Err("too many limited caves to track their revisits".into());
+                }
+                tracked[small[v]] = Some(offset);
+                offset += width;
+            }
+        }
+
+        let mut edges: Vec<HashMap<usize, u128>> = vec![HashMap::new(); count];
+        for v in (0..caves.len()).filter(|&v| !caves.is_big(v)) {
+            for &n in caves.graph[v].iter() {
+                let n = n & VALUE_MASK;
+                if !caves.is_big(n) {
+                    *edges[small[v]].entry(small[n]).or_default() += 1;
+                    continue;
+                }
+                for &m in caves.graph[n].iter() {
+                    let m = m & VALUE_MASK;
+                    if caves.is_big(m) {
+                        return Err(format!(
+                            "big caves {} and {} are adjacent, the amount of paths is infinite",
+                            caves.name(n),
+                            caves.name(m)
+                        )
+                        .into());
+                    }
+                    *edges[small[v]].entry(small[m]).or_default() += 1;
+                }
+            }
+        }
+
+        Ok(Counter {
+            edges: edges
+                .into_iter()
+                .map(|e| {
+                    let mut e: Vec<_> = e.into_iter().collect();
+                    e.sort_unstable();
+                    e
+                })
+                .collect(),
+            limits,
+            tracked,
+            counter_mask: 1u64.checked_shl(width).map_or(u64::MAX, |m| m - 1),
+            memo: HashMap::default(),
+        })
+    }
 
-    let mut count = 0;
-    for &n in &g[v & VALUE_MASK] {
-        let was_visited = visited[n & VALUE_MASK];
-        if !was_visited || (can_visit_small && n != START_VERTICE && n != END_VERTICE) {
-            count += if n == END_VERTICE {
+    // count returns the amount of paths from v to end, where visited is the set
+    // of visited small caves, budget is the amount of allowed revisits and
+    // revisits holds the packed amounts of revisits of tracked caves.
+    fn count(&mut self, v: usize, visited: u64, budget: usize, revisits: u64) -> Result<u128> {
+        let key = (v, visited, budget, revisits);
+        if let Some(&count) = self.memo.get(&key) {
+            return Ok(count);
+        }
+
+        let mut count: u128 = 0;
+        for i in 0..self.edges[v].len() {
+            let (n, ways) = self.edges[v][i];
+            let paths = if n == END_VERTICE {
                 1
+            } else if self.limits[n] == 0 {
+                continue;
+            } else if visited & (1 << n) == 0 {
+                self.count(n, visited | (1 << n), budget, revisits)?
+            } else if budget == 0 || self.limits[n] < 2 {
+                continue;
+            } else if let Some(offset) = self.tracked[n] {
+                let made = (revisits >> offset) & self.counter_mask;
+                if made as usize + 2 > self.limits[n] {
+                    continue;
+                }
+                self.count(n, visited, budget - 1, revisits + (1 << offset))?
             } else {
-                dfs(n, g, can_visit_small && !was_visited, visited)
+                self.count(n, visited, budget - 1, revisits)?
             };
-            visited[n & VALUE_MASK] = was_visited;
+            count = paths
+                .checked_mul(ways)
+                .and_then(|p| count.checked_add(p))
+                .ok_or("amount of paths overflows u128")?;
+        }
+
+        self.memo.insert(key, count);
+        Ok(count)
+    }
+}
+
+// count_paths returns the amount of paths from start to end
+// where small caves can be revisited at most budget times in total.
+pub fn count_paths(caves: &Caves, budget: usize) -> Result<u128> {
+    Counter::new(caves, budget)?.count(START_VERTICE, 1 << START_VERTICE, budget, 0)
+}
+
+pub fn part_with(input: &str, rules: &Rules) -> Result<u128> {
+    count_paths(&parse_with(input, rules)?, rules.budget)
+}
+
+pub fn part1(input: &str) -> Result<u128> {
+    part_with(input, &Rules::default())
+}
+
+pub fn part2(input: &str) -> Result<u128> {
+    let rules = Rules {
+        budget: 1,
+        ..Rules::default()
+    };
+    part_with(input, &rules)
+}
+
+// PathFilter restricts enumerated paths.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    // Caves every path must pass through.
+    pub must_visit: Vec<String>,
+    // Caves no path may pass through.
+    pub avoid: Vec<String>,
+    // Maximum amount of edges in a path.
+    pub max_len: Option<usize>,
+}
+
+struct Frame {
+    v: usize,
+    next: usize,
+    budget: usize,
+}
+
+// Paths iterates over paths from start to end in depth-first order.
+pub struct Paths<'a> {
+    caves: &'a Caves,
+    visits: Vec<usize>,
+    stack: Vec<Frame>,
+    must_visit: Vec<usize>,
+    avoid: Vec<bool>,
+    max_len: usize,
+}
+
+impl Caves {
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn name(&self, v: usize) -> &str {
+        &self.names[v & VALUE_MASK]
+    }
+
+    pub fn is_big(&self, v: usize) -> bool {
+        self.big[v & VALUE_MASK]
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    // paths returns an iterator over all paths satisfying the filter.
+    // Small caves can be revisited at most budget times in total.
+    pub fn paths(&self, budget: usize, filter: &PathFilter) -> Paths<'_> {
+        let mut avoid = vec![false; self.len()];
+        for v in filter.avoid.iter().filter_map(|name| self.find(name)) {
+            avoid[v] = true;
+        }
+
+        let must_visit: Option<Vec<usize>> = filter
+            .must_visit
+            .iter()
+            .map(|name| self.find(name))
+            .collect();
+
+        // No path visits a cave which doesn't exist.
+        let mut stack = Vec::new();
+        if must_visit.is_some() && !avoid[START_VERTICE] {
+            stack.push(Frame {
+                v: START_VERTICE,
+                next: 0,
+                budget,
+            });
+        }
+
+        let mut visits = vec![0; self.len()];
+        visits[START_VERTICE] = 1;
+        Paths {
+            caves: self,
+            visits,
+            stack,
+            must_visit: must_visit.unwrap_or_default(),
+            avoid,
+            max_len: filter.max_len.unwrap_or(usize::MAX),
         }
     }
+}
+
+impl<'a> Iterator for Paths<'a> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let caves = self.caves;
+        while let Some(top) = self.stack.last_mut() {
+            let v = top.v & VALUE_MASK;
+            if top.next == caves.graph[v].len() {
+                self.visits[v] -= 1;
+                self.stack.pop();
+                continue;
+            }
+
+            let n = caves.graph[v][top.next];
+            let budget = top.budget;
+            top.next += 1;
+
+            let (big, n) = (n & (1 << UPPER_BIT) != 0, n & VALUE_MASK);
+            let visits = self.visits[n];
+            let revisit = !big && visits > 0;
+            if (!big && visits >= caves.limits[n])
+                || (revisit && budget == 0)
+                || self.avoid[n]
+                || self.stack.len() > self.max_len
+            {
+                continue;
+            }
+            if n == END_VERTICE {
+                let on_path = |c: &usize| self.stack.iter().any(|f| f.v == *c);
+                if self
+                    .must_visit
+                    .iter()
+                    .all(|c| *c == END_VERTICE || on_path(c))
+                {
+                    let mut path: Vec<&'a str> =
+                        self.stack.iter().map(|f| caves.name(f.v)).collect();
+                    path.push(caves.name(END_VERTICE));
+                    return Some(path);
+                }
+                continue;
+            }
+
+            self.visits[n] += 1;
+            self.stack.push(Frame {
+                v: n,
+                next: 0,
+                budget: budget - revisit as usize,
+            });
+        }
+        None
+    }
+}
+
+fn dot_id(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl Caves {
+    // write_dot writes the cave system in Graphviz DOT format.
+    // Edges between consecutive caves of path are coloured.
+    pub fn write_dot(&self, out: &mut impl Write, path: &[&str]) -> Result<()> {
+        let mut highlighted = Vec::new();
+        for pair in path.windows(2) {
+            let a = self
+                .find(pair[0])
+                .ok_or_else(|| format!("unknown cave: {}", pair[0]))?;
+            let b = self
+                .find(pair[1])
+                .ok_or_else(|| format!("unknown cave: {}", pair[1]))?;
+            if !self.graph[a].iter().any(|&n| n & VALUE_MASK == b) {
+                return Err(format!("caves {} and {} are not connected", pair[0], pair[1]).into());
+            }
+            highlighted.push((a.min(b), a.max(b)));
+        }
+
+        writeln!(out, "graph caves {{")?;
+        for (v, name) in self.names.iter().enumerate() {
+            let shape = if self.big[v] { "box" } else { "ellipse" };
+            let style = match v {
+                START_VERTICE => ", style=filled, fillcolor=palegreen",
+                END_VERTICE => ", style=filled, fillcolor=lightcoral",
+                _ => "",
+            };
+            writeln!(out, "    {} [shape={}{}];", dot_id(name), shape, style)?;
+        }
 
-    visited[v & VALUE_MASK] = false;
+        for (v, neighbours) in self.graph.iter().enumerate() {
+            // Every edge is stored twice, loops are stored twice in the same list.
+            let mut loops = 0;
+            for n in neighbours.iter().map(|&n| n & VALUE_MASK) {
+                if n == v {
+                    loops += 1;
+                    if loops % 2 == 0 {
+                        continue;
+                    }
+                } else if n < v {
+                    continue;
+                }
+                let attrs = if highlighted.contains(&(v, n)) {
+                    " [color=red, penwidth=2]"
+                } else {
+                    ""
+                };
+                writeln!(
+                    out,
+                    "    {} -- {}{};",
+                    dot_id(&self.names[v]),
+                    dot_id(&self.names[n]),
+                    attrs
+                )?;
+            }
+        }
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+}
 
-    count
+// write_text writes every path on a separate line with caves separated by commas.
+pub fn write_text<'a>(
+    out: &mut impl Write,
+    paths: impl Iterator<Item = Vec<&'a str>>,
+) -> io::Result<()> {
+    for path in paths {
+        writeln!(out, "{}", path.join(","))?;
+    }
+    Ok(())
 }
 
-pub fn part1(input: &str) -> usize {
-    let g = parse(input);
-    dfs(START_VERTICE, &g, false, &mut vec![false; g.len()])
+fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
 }
 
-pub fn part2(input: &str) -> usize {
-    let g = parse(input);
-    dfs(START_VERTICE, &g, true, &mut vec![false; g.len()])
+// write_json writes paths as a JSON array of arrays of cave names.
+pub fn write_json<'a>(
+    out: &mut impl Write,
+    paths: impl Iterator<Item = Vec<&'a str>>,
+) -> io::Result<()> {
+    write!(out, "[")?;
+    for (i, path) in paths.enumerate() {
+        let names: Vec<String> = path.iter().map(|name| json_string(name)).collect();
+        let sep = if i == 0 { "" } else { "," };
+        write!(out, "{}[{}]", sep, names.join(","))?;
+    }
+    writeln!(out, "]")
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{part1, part2};
+    use crate::{
+        count_paths, parse, parse_with, part1, part2, part_with, write_json, write_text,
+        PathFilter, Rules,
+    };
 
     #[test]
     fn example() {
         let input = "start-A\nstart-b\nA-c\nA-b\nb-d\nA-end\nb-end";
-        assert_eq!(10, part1(&input));
-        assert_eq!(36, part2(&input));
+        assert_eq!(10, part1(input).unwrap());
+        assert_eq!(36, part2(input).unwrap());
 
         let input =
             "dc-end\nHN-start\nstart-kj\ndc-start\ndc-HN\nLN-dc\nHN-end\nkj-sa\nkj-HN\nkj-dc";
-        assert_eq!(19, part1(&input));
-        assert_eq!(103, part2(&input));
+        assert_eq!(19, part1(input).unwrap());
+        assert_eq!(103, part2(input).unwrap());
 
         let input = "fs-end\nhe-DX\nfs-he\nstart-DX\npj-DX\nend-zg\nzg-sl\nzg-pj\npj-he\nRW-he\nfs-DX\npj-RW\nzg-RW\nstart-pj\nhe-WI\nzg-he\npj-fs\nstart-RW";
-        assert_eq!(226, part1(&input));
-        assert_eq!(3509, part2(&input));
+        assert_eq!(226, part1(input).unwrap());
+        assert_eq!(3509, part2(input).unwrap());
+    }
+
+    #[test]
+    fn paths() {
+        let input = "start-A\nstart-b\nA-c\nA-b\nb-d\nA-end\nb-end";
+        let caves = parse(input).unwrap();
+        let all = PathFilter::default();
+        assert_eq!(10, caves.paths(0, &all).count());
+        assert_eq!(36, caves.paths(1, &all).count());
+
+        let filter = PathFilter {
+            must_visit: vec!["c".to_string()],
+            avoid: vec!["b".to_string()],
+            max_len: None,
+        };
+        let mut paths: Vec<_> = caves.paths(0, &filter).collect();
+        paths.sort();
+        assert_eq!(vec![vec!["start", "A", "c", "A", "end"]], paths);
+
+        let filter = PathFilter {
+            max_len: Some(2),
+            ..PathFilter::default()
+        };
+        let mut out = Vec::new();
+        write_text(&mut out, caves.paths(0, &filter)).unwrap();
+        let mut lines: Vec<_> = std::str::from_utf8(&out).unwrap().lines().collect();
+        lines.sort_unstable();
+        assert_eq!(vec!["start,A,end", "start,b,end"], lines);
+
+        let filter = PathFilter {
+            must_visit: vec!["d".to_string()],
+            ..PathFilter::default()
+        };
+        let mut out = Vec::new();
+        write_json(&mut out, caves.paths(0, &filter)).unwrap();
+        assert_eq!("[]\n", std::str::from_utf8(&out).unwrap());
+
+        let filter = PathFilter {
+            must_visit: vec!["d".to_string()],
+            avoid: vec!["A".to_string()],
+            max_len: None,
+        };
+        let mut out = Vec::new();
+        write_json(&mut out, caves.paths(1, &filter)).unwrap();
+        assert_eq!(
+            "[[\"start\",\"b\",\"d\",\"b\",\"end\"]]\n",
+            std::str::from_utf8(&out).unwrap()
+        );
+    }
+    #[test]
+    fn dense() {
+        // Every pair of small caves is connected directly and through a big cave.
+        let names: Vec<String> = (0..15).map(|i| format!("c{}", i)).collect();
+        let mut input = String::new();
+        for (i, a) in names.iter().enumerate() {
+            input += &format!("start-{}\n{}-end\n{}-HUB\n", a, a, a);
+            for b in names[i + 1..].iter() {
+                input += &format!("{}-{}\n", a, b);
+            }
+        }
+        input += "start-end";
+
+        // A path through k > 0 caves goes through k - 1 edges between them,
+        // each of which can be taken in 2 ways.
+        let mut expected = 1u128;
+        let mut orderings = 1u128;
+        for k in 1..=15u32 {
+            orderings *= 16 - k as u128;
+            expected += orderings << (k - 1);
+        }
+        assert_eq!(expected, part1(&input).unwrap());
+
+        let caves = parse("start-a\na-B\nB-C\nC-end").unwrap();
+        assert!(count_paths(&caves, 0).is_err());
+    }
+    #[test]
+    fn rules() {
+        let input = "start-A\nstart-b\nA-c\nA-b\nb-d\nA-end\nb-end";
+        let all = PathFilter::default();
+        for budget in 0..4 {
+            let caves = parse(input).unwrap();
+            let count = caves.paths(budget, &all).count() as u128;
+            assert_eq!(count, count_paths(&caves, budget).unwrap());
+        }
+
+        // Cave c can be visited at most twice and d only once.
+        let rules = Rules {
+            budget: 3,
+            limits: [("c".to_string(), 2), ("d".to_string(), 1)].into(),
+            ..Rules::default()
+        };
+        let caves = parse_with(input, &rules).unwrap();
+        let paths: Vec<_> = caves.paths(rules.budget, &all).collect();
+        assert!(paths
+            .iter()
+            .all(|p| p.iter().filter(|&&c| c == "c").count() <= 2));
+        assert!(paths
+            .iter()
+            .all(|p| p.iter().filter(|&&c| c == "d").count() <= 1));
+        assert_eq!(paths.len() as u128, part_with(input, &rules).unwrap());
+
+        let rules = Rules {
+            start: "in".to_string(),
+            end: "out".to_string(),
+            is_big: Box::new(|name| name.starts_with('_')),
+            ..Rules::default()
+        };
+        let input = "in-_a\nin-b\n_a-c\n_a-b\nb-d\n_a-out\nb-out";
+        assert_eq!(10, part_with(input, &rules).unwrap());
+
+        let rules = Rules {
+            limits: [("_a".to_string(), 1)].into(),
+            ..rules
+        };
+        assert!(parse_with(input, &rules).is_err());
+        assert!(parse_with(
+            "A-B",
+            &Rules {
+                end: "start".to_string(),
+                ..Rules::default()
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn dot() {
+        let caves = parse("start-A\nA-b\nA-end\nb-end").unwrap();
+        let mut out = Vec::new();
+        caves
+            .write_dot(&mut out, &["start", "A", "b", "end"])
+            .unwrap();
+        assert_eq!(
+            "graph caves {
+    \"start\" [shape=ellipse, style=filled, fillcolor=palegreen];
+    \"end\" [shape=ellipse, style=filled, fillcolor=lightcoral];
+    \"A\" [shape=box];
+    \"b\" [shape=ellipse];
+    \"start\" -- \"A\" [color=red, penwidth=2];
+    \"end\" -- \"A\";
+    \"end\" -- \"b\" [color=red, penwidth=2];
+    \"A\" -- \"b\" [color=red, penwidth=2];
+}
+",
+            std::str::from_utf8(&out).unwrap()
+        );
+        assert!(caves.write_dot(&mut Vec::new(), &["start", "b"]).is_err());
     }
 }
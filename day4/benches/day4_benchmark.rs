@@ -21,5 +21,12 @@ pub fn part2_bench(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, part1_bench, part2_bench);
+pub fn play_bench(c: &mut Criterion) {
+    let (numbers, tables) = get_input("input").unwrap();
+    c.bench_function("play", |b| {
+        b.iter(|| play(black_box(&numbers), black_box(&tables)))
+    });
+}
+
+criterion_group!(benches, part1_bench, part2_bench, play_bench);
 criterion_main!(benches);
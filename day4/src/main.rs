@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+use std::env;
 use std::error::Error;
 use std::io::{self, Read, Write};
 
@@ -18,6 +20,11 @@ impl Mask {
     const ROW: usize = 0x1F;
     const COL: usize = 1 << 20 | 1 << 15 | 1 << 10 | 1 << 5 | 1 << 0;
 
+    #[inline]
+    fn is_won(&self) -> bool {
+        self.mask & (1 << 25) != 0
+    }
+
     fn mark(&mut self, n: usize) -> bool {
         let mask = self.mask | (1 << n);
         let row = Mask::ROW << (n / 5 * 5);
@@ -29,15 +36,6 @@ impl Mask {
 }
 
 impl Table {
-    fn mark(&self, n: usize, m: &mut Mask) -> bool {
-        for (i, num) in self.x.iter().enumerate() {
-            if *num == n {
-                return m.mark(i);
-            }
-        }
-        false
-    }
-
     fn sum(&self, m: &Mask) -> usize {
         let mut sum = 0_usize;
         for i in 0..25 {
@@ -49,6 +47,49 @@ impl Table {
     }
 }
 
+// Index maps every number to the (board, cell) positions it occupies.
+struct Index {
+    cells: HashMap<usize, Vec<(usize, usize)>>,
+}
+
+impl Index {
+    fn new(tables: &[Table]) -> Index {
+        let mut cells = HashMap::<usize, Vec<(usize, usize)>>::new();
+        for (i, t) in tables.iter().enumerate() {
+            for (j, &n) in t.x.iter().enumerate() {
+                cells.entry(n).or_default().push((i, j));
+            }
+        }
+        Index { cells }
+    }
+
+    #[inline]
+    fn get(&self, n: usize) -> &[(usize, usize)] {
+        self.cells.get(&n).map_or(&[], |v| v.as_slice())
+    }
+}
+
+// finish draws numbers once and returns final masks together with
+// (board, draw index) pairs in the order boards have won.
+// Masks of won boards are not marked further.
+fn finish(numbers: &[usize], tables: &[Table], index: &Index) -> (Vec<Mask>, Vec<(usize, usize)>) {
+    let mut masks = vec![Mask { mask: 0 }; tables.len()];
+    let mut order = Vec::new();
+
+    for (j, &n) in numbers.iter().enumerate() {
+        if order.len() == tables.len() {
+            break;
+        }
+        for &(i, cell) in index.get(n) {
+            let m = &mut masks[i];
+            if !m.is_won() && m.mark(cell) {
+                order.push((i, j));
+            }
+        }
+    }
+    (masks, order)
+}
+
 pub fn main() -> Result<()> {
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
@@ -61,6 +102,31 @@ pub fn main() -> Result<()> {
     let (last_number, sum) = part2(&numbers, &tables);
     io::stdout().write_fmt(format_args!("Part 2: {}\n", last_number * sum))?;
 
+    match env::args().nth(1).as_deref() {
+        Some("--report") => {
+            io::stdout().write_all(report_text(&play(&numbers, &tables)).as_bytes())?
+        }
+        Some("--json") => {
+            io::stdout().write_all(report_json(&play(&numbers, &tables)).as_bytes())?
+        }
+        Some("--simulate") => {
+            let trials = env::args().nth(2).map_or(Ok(1000), |s| s.parse())?;
+            let seed = env::args().nth(3).map_or(Ok(0), |s| s.parse())?;
+            let odds = simulate(&numbers, &tables, trials, seed)?;
+            io::stdout().write_all(b"board  first   last  draws\n")?;
+            for (i, o) in odds.iter().enumerate() {
+                io::stdout().write_fmt(format_args!(
+                    "{:>5} {:>6.4} {:>6.4} {:>6.2}\n",
+                    i,
+                    o.first,
+                    o.last,
+                    o.expected_draws.unwrap_or(f64::NAN)
+                ))?;
+            }
+        }
+        _ => {}
+    }
+
     Ok(())
 }
 
@@ -90,46 +156,200 @@ pub fn parse(input: &str) -> Result<(Vec<usize>, Vec<Table>)> {
 }
 
 pub fn part1(numbers: &[usize], tables: &[Table]) -> (usize, usize) {
-    let mut masks = vec![Mask { mask: 0 }; tables.len()];
+    let (masks, order) = finish(numbers, tables, &Index::new(tables));
+    match order.first() {
+        Some(&(i, j)) => (numbers[j], tables[i].sum(&masks[i])),
+        None => (0, 0),
+    }
+}
 
-    for &n in numbers {
-        for (t, m) in tables.iter().zip(masks.iter_mut()) {
-            if t.mark(n, m) {
-                return (n, t.sum(m));
-            }
+pub fn part2(numbers: &[usize], tables: &[Table]) -> (usize, usize) {
+    let (masks, order) = finish(numbers, tables, &Index::new(tables));
+    let last = match order.last() {
+        Some(&(_, j)) => j,
+        None => return (0, 0),
+    };
+    // Among boards winning on the last draw, report the first one.
+    let (i, j) = order[order.partition_point(|&(_, j)| j < last)];
+    (numbers[j], tables[i].sum(&masks[i]))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoardResult {
+    pub board: usize,
+    // Index of the winning draw and the number drawn, None if the board never won.
+    pub win: Option<(usize, usize)>,
+    pub unmarked: usize,
+    pub score: usize,
+}
+
+// play draws all numbers once and returns results for every board
+// in the order boards have finished. Boards which never won come last.
+pub fn play(numbers: &[usize], tables: &[Table]) -> Vec<BoardResult> {
+    let (masks, order) = finish(numbers, tables, &Index::new(tables));
+    let mut results = Vec::with_capacity(tables.len());
+
+    for &(i, j) in order.iter() {
+        let n = numbers[j];
+        let unmarked = tables[i].sum(&masks[i]);
+        results.push(BoardResult {
+            board: i,
+            win: Some((j, n)),
+            unmarked,
+            score: n * unmarked,
+        });
+    }
+
+    for (i, (t, m)) in tables.iter().zip(masks.iter()).enumerate() {
+        if !m.is_won() {
+            results.push(BoardResult {
+                board: i,
+                win: None,
+                unmarked: t.sum(m),
+                score: 0,
+            });
         }
     }
-    (0, 0)
+    results
 }
 
-pub fn part2(numbers: &[usize], tables: &[Table]) -> (usize, usize) {
-    let mut max_mask = Mask { mask: 0 };
-    let mut max_index = usize::MIN;
-    let mut max_index_table = usize::MIN;
-
-    for (i, t) in tables.iter().enumerate() {
-        let mut mask = Mask { mask: 0 };
-        for (j, &n) in numbers.iter().enumerate() {
-            if t.mark(n, &mut mask) {
-                if j > max_index {
-                    max_index = j;
-                    max_index_table = i;
-                    max_mask = mask;
-                }
-                break;
-            }
+pub fn report_text(results: &[BoardResult]) -> String {
+    let mut s = String::from("rank board  draw number unmarked  score\n");
+    for (rank, r) in results.iter().enumerate() {
+        match r.win {
+            Some((j, n)) => s.push_str(&format!(
+                "{:>4} {:>5} {:>5} {:>6} {:>8} {:>6}\n",
+                rank + 1,
+                r.board,
+                j,
+                n,
+                r.unmarked,
+                r.score
+            )),
+            None => s.push_str(&format!(
+                "{:>4} {:>5} {:>5} {:>6} {:>8} {:>6}\n",
+                "-", r.board, "never", "-", r.unmarked, r.score
+            )),
+        }
+    }
+    s
+}
+
+pub fn report_json(results: &[BoardResult]) -> String {
+    let boards: Vec<String> = results
+        .iter()
+        .enumerate()
+        .map(|(rank, r)| {
+            let (rank, draw, number) = match r.win {
+                Some((j, n)) => ((rank + 1).to_string(), j.to_string(), n.to_string()),
+                None => ("null".to_string(), "null".to_string(), "null".to_string()),
+            };
+            format!(
+                "{{\"rank\":{},\"board\":{},\"draw\":{},\"number\":{},\"unmarked\":{},\"score\":{}}}",
+                rank, r.board, draw, number, r.unmarked, r.score
+            )
+        })
+        .collect();
+    format!("[{}]\n", boards.join(","))
+}
+
+// Rng is a xorshift64* generator, good enough to shuffle draws.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // Nearby seeds are spread with splitmix64 finaliser, which is a bijection.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        // Zero is a fixed point of xorshift.
+        let state = if z == 0 { 0x9E37_79B9_7F4A_7C15 } else { z };
+        Rng { state }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // below returns a number in 0..n, n must be non-zero.
+    fn below(&mut self, n: usize) -> usize {
+        (((self.next_u64() as u128) * (n as u128)) >> 64) as usize
+    }
+
+    pub fn shuffle<T>(&mut self, v: &mut [T]) {
+        for i in (1..v.len()).rev() {
+            v.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Odds {
+    // Probability of winning on the first (last) winning draw, ties are counted for every board.
+    pub first: f64,
+    pub last: f64,
+    // Mean number of draws until the board wins, None if it has never won.
+    pub expected_draws: Option<f64>,
+}
+
+// simulate plays trials games with shuffled draw order and estimates
+// winning odds for every board.
+pub fn simulate(
+    numbers: &[usize],
+    tables: &[Table],
+    trials: usize,
+    seed: u64,
+) -> Result<Vec<Odds>> {
+    if trials == 0 {
+        return Err("at least one trial is needed".into());
+    }
+
+    let index = Index::new(tables);
+    let mut rng = Rng::new(seed);
+    let mut draws = numbers.to_vec();
+
+    let mut first = vec![0_usize; tables.len()];
+    let mut last = vec![0_usize; tables.len()];
+    let mut wins = vec![0_usize; tables.len()];
+    let mut total = vec![0_usize; tables.len()];
+
+    for _ in 0..trials {
+        rng.shuffle(&mut draws);
+
+        let (_, order) = finish(&draws, tables, &index);
+        let (min, max) = match (order.first(), order.last()) {
+            (Some(&(_, a)), Some(&(_, b))) => (a, b),
+            _ => continue,
+        };
+        for &(i, j) in order.iter() {
+            first[i] += (j == min) as usize;
+            last[i] += (j == max) as usize;
+            wins[i] += 1;
+            total[i] += j + 1;
         }
     }
-    (numbers[max_index], tables[max_index_table].sum(&max_mask))
+
+    Ok((0..tables.len())
+        .map(|i| Odds {
+            first: first[i] as f64 / trials as f64,
+            last: last[i] as f64 / trials as f64,
+            expected_draws: (wins[i] != 0).then(|| total[i] as f64 / wins[i] as f64),
+        })
+        .collect())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{parse, part1, part2};
+    use crate::{parse, part1, part2, play, report_json, simulate, BoardResult, Rng};
 
-    #[test]
-    fn simple() {
-        let input = "7,4,9,5,11,17,23,2,0,14,21,24,10,16,13,6,15,25,12,22,18,20,8,19,3,26,1
+    const INPUT: &str = "7,4,9,5,11,17,23,2,0,14,21,24,10,16,13,6,15,25,12,22,18,20,8,19,3,26,1
 
         22 13 17 11  0
          8  2 23  4 24
@@ -148,7 +368,10 @@ mod tests {
         18  8 23 26 20
         22 11 13  6  5
          2  0 12  3  7";
-        let (size, params) = parse(input).unwrap();
+
+    #[test]
+    fn simple() {
+        let (size, params) = parse(INPUT).unwrap();
 
         let (last_number, sum) = part1(&size, &params);
         assert_eq!(24, last_number);
@@ -158,4 +381,79 @@ mod tests {
         assert_eq!(13, last_number);
         assert_eq!(148, sum);
     }
+
+    #[test]
+    fn finishing_order() {
+        let (numbers, tables) = parse(INPUT).unwrap();
+
+        let results = play(&numbers, &tables);
+        assert_eq!(
+            vec![
+                BoardResult {
+                    board: 2,
+                    win: Some((11, 24)),
+                    unmarked: 188,
+                    score: 4512
+                },
+                BoardResult {
+                    board: 0,
+                    win: Some((13, 16)),
+                    unmarked: 137,
+                    score: 2192
+                },
+                BoardResult {
+                    board: 1,
+                    win: Some((14, 13)),
+                    unmarked: 148,
+                    score: 1924
+                },
+            ],
+            results
+        );
+
+        let results = play(&numbers[..12], &tables);
+        assert_eq!(None, results[2].win);
+        assert!(report_json(&results).ends_with(
+            "{\"rank\":null,\"board\":1,\"draw\":null,\"number\":null,\"unmarked\":187,\"score\":0}]\n"
+        ));
+    }
+
+    #[test]
+    fn same_draw() {
+        let (numbers, mut tables) = parse(INPUT).unwrap();
+        tables.extend_from_slice(&tables.clone());
+
+        assert_eq!((24, 188), part1(&numbers, &tables));
+        assert_eq!((13, 148), part2(&numbers, &tables));
+
+        let boards: Vec<usize> = play(&numbers, &tables).iter().map(|r| r.board).collect();
+        assert_eq!(vec![2, 5, 0, 3, 1, 4], boards);
+    }
+
+    #[test]
+    fn monte_carlo() {
+        let (numbers, tables) = parse(INPUT).unwrap();
+
+        let mut v: Vec<usize> = (0..10).collect();
+        Rng::new(1).shuffle(&mut v);
+        v.sort_unstable();
+        assert_eq!((0..10).collect::<Vec<usize>>(), v);
+
+        let odds = simulate(&numbers, &tables, 1000, 42).unwrap();
+        assert_eq!(odds, simulate(&numbers, &tables, 1000, 42).unwrap());
+        assert_ne!(
+            simulate(&numbers, &tables, 100, 1).unwrap(),
+            simulate(&numbers, &tables, 100, 2).unwrap()
+        );
+        assert!(simulate(&numbers, &tables, 0, 42).is_err());
+        for o in odds.iter() {
+            assert!(0.0 < o.first && o.first < 1.0);
+            assert!(0.0 < o.last && o.last < 1.0);
+            let draws = o.expected_draws.unwrap();
+            assert!((5.0..=27.0).contains(&draws));
+        }
+
+        let first: f64 = odds.iter().map(|o| o.first).sum();
+        assert!(first >= 1.0);
+    }
 }
@@ -1,30 +1,46 @@
-use std::cmp::{min, Ordering};
-use std::collections::HashSet;
-use std::io::{self, Read, Write};
+use std::cmp::{max, min, Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
 
-type Result<T> = ::std::result::Result<T, Box<dyn ::std::error::Error>>;
+pub type Result<T> = ::std::result::Result<T, Box<dyn ::std::error::Error>>;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
-struct Point {
-    x: isize,
-    y: isize,
+pub struct Point {
+    pub x: isize,
+    pub y: isize,
 }
 
 #[derive(Debug)]
-struct Line {
+pub struct Line {
     a: Point,
     b: Point,
+    // Smallest step from a to b between lattice points.
+    step: Point,
+}
+
+fn gcd(mut a: isize, mut b: isize) -> isize {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
 }
 
 impl Line {
     fn new(ax: isize, ay: isize, bx: isize, by: isize) -> Line {
-        let a = Point { x: ax, y: ay };
-        let b = Point { x: bx, y: by };
-        if a.cmp(&b).is_le() {
-            Line { a, b }
+        let (a, b) = min_max(Point { x: ax, y: ay }, Point { x: bx, y: by });
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        let steps = gcd(dx, dy);
+        let step = if steps == 0 {
+            Point { x: 0, y: 0 }
         } else {
-            Line { a: b, b: a }
-        }
+            Point {
+                x: dx / steps,
+                y: dy / steps,
+            }
+        };
+        Line { a, b, step }
     }
 
     #[inline]
@@ -43,69 +59,193 @@ impl Line {
     }
 
     #[inline]
-    fn get_equation(&self) -> (isize, isize, isize) {
-        (
-            self.b.y - self.a.y,
-            self.a.x - self.b.x,
-            self.b.x * self.a.y - self.a.x * self.b.y,
-        )
+    fn min_y(&self) -> isize {
+        min(self.a.y, self.b.y)
     }
 
-    fn intersect(&self, other: &Line) -> Option<(Point, Option<Point>)> {
-        let (a1, b1, c1) = self.get_equation();
-
-        debug_assert_eq!(0, a1 * self.a.x + b1 * self.a.y + c1);
-        debug_assert_eq!(0, a1 * self.b.x + b1 * self.b.y + c1);
+    #[inline]
+    fn max_y(&self) -> isize {
+        max(self.a.y, self.b.y)
+    }
 
-        let r3 = a1 * other.a.x + b1 * other.a.y + c1;
-        let r4 = a1 * other.b.x + b1 * other.b.y + c1;
-        if r3 * r4 > 0 {
-            return None;
+    // span returns the range of x covered by the line in row y,
+    // None if the line doesn't cross row y at a lattice point.
+    #[inline]
+    fn span(&self, y: isize) -> Option<(isize, isize)> {
+        if self.is_horizontal() {
+            return Some((self.a.x, self.b.x));
         }
+        let k = y - self.a.y;
+        (k % self.step.y == 0).then(|| {
+            let x = self.a.x + k / self.step.y * self.step.x;
+            (x, x)
+        })
+    }
+}
 
-        let (a2, b2, c2) = other.get_equation();
+#[inline]
+fn min_max(a: Point, b: Point) -> (Point, Point) {
+    if a.cmp(&b).is_le() {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
 
-        debug_assert_eq!(0, a2 * other.a.x + b2 * other.a.y + c2);
-        debug_assert_eq!(0, a2 * other.b.x + b2 * other.b.y + c2);
+// Run is a horizontal run of points [x1, x2) in row y covered by count lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Run {
+    pub y: isize,
+    pub x1: isize,
+    pub x2: isize,
+    pub count: usize,
+}
 
-        let r1 = a2 * self.a.x + b2 * self.a.y + c2;
-        let r2 = a2 * self.b.x + b2 * self.b.y + c2;
-        if r1 * r2 > 0 {
-            return None;
-        }
+// Coverage contains amount of lines covering every lattice point
+// stored as runs sorted by row and column.
+pub struct Coverage {
+    runs: Vec<Run>,
+}
 
-        let d = a1 * b2 - a2 * b1;
-        if d == 0 {
-            return if self.a.cmp(&other.a).is_le() {
-                match self.b.cmp(&other.a) {
-                    Ordering::Less => None,
-                    Ordering::Equal => Some((self.b, None)),
-                    Ordering::Greater => Some((other.a, Some(min(self.b, other.b)))),
+impl Coverage {
+    // new sweeps rows from top to bottom and calculates coverage for every row.
+    // For every row only lines crossing it are considered.
+    pub fn new<'a>(lines: impl Iterator<Item = &'a Line>) -> Coverage {
+        let mut lines: Vec<&Line> = lines.collect();
+        lines.sort_unstable_by_key(|l| l.min_y());
+
+        let mut runs = Vec::new();
+        let mut active: Vec<&Line> = Vec::new();
+        let mut events: Vec<(isize, isize)> = Vec::new();
+        let mut next = 0;
+        let mut y = match lines.first() {
+            Some(l) => l.min_y(),
+            None => return Coverage { runs },
+        };
+
+        loop {
+            active.retain(|l| l.max_y() >= y);
+            if active.is_empty() {
+                match lines.get(next) {
+                    Some(l) => y = max(y, l.min_y()),
+                    None => break,
                 }
-            } else {
-                match self.a.cmp(&other.b) {
-                    Ordering::Less => None,
-                    Ordering::Equal => Some((self.a, None)),
-                    Ordering::Greater => Some((self.a, Some(min(self.b, other.b)))),
+            }
+            while next < lines.len() && lines[next].min_y() == y {
+                active.push(lines[next]);
+                next += 1;
+            }
+
+            events.clear();
+            for (x1, x2) in active.iter().filter_map(|l| l.span(y)) {
+                events.push((x1, 1));
+                events.push((x2 + 1, -1));
+            }
+            events.sort_unstable();
+
+            let mut covered = 0;
+            let mut last = 0;
+            for &(x, d) in events.iter() {
+                if covered > 0 && x > last {
+                    runs.push(Run {
+                        y,
+                        x1: last,
+                        x2: x,
+                        count: covered as usize,
+                    });
                 }
-            };
+                covered += d;
+                last = x;
+            }
+            y += 1;
         }
+        Coverage { runs }
+    }
 
-        let xd = b1 * c2 - b2 * c1;
-        let yd = a2 * c1 - a1 * c2;
-        (xd % d == 0 && yd % d == 0).then(|| {
-            (
-                Point {
-                    x: xd / d,
-                    y: yd / d,
-                },
-                None,
-            )
-        })
+    // count_at_least returns the amount of points covered by at least k lines.
+    pub fn count_at_least(&self, k: usize) -> usize {
+        self.runs
+            .iter()
+            .filter(|r| r.count >= k)
+            .map(|r| (r.x2 - r.x1) as usize)
+            .sum()
+    }
+
+    pub fn get(&self, p: Point) -> usize {
+        let i = self.runs.partition_point(|r| (r.y, r.x2) <= (p.y, p.x));
+        match self.runs.get(i) {
+            Some(r) if r.y == p.y && r.x1 <= p.x => r.count,
+            _ => 0,
+        }
+    }
+
+    // points returns all covered points together with the amount of lines covering them.
+    pub fn points(&self) -> impl Iterator<Item = (Point, usize)> + '_ {
+        self.runs
+            .iter()
+            .flat_map(|r| (r.x1..r.x2).map(move |x| (Point { x, y: r.y }, r.count)))
+    }
+
+    // in_box returns covered points inside of a bounding box with corners min and max inclusive.
+    pub fn in_box(&self, min: Point, max: Point) -> impl Iterator<Item = (Point, usize)> + '_ {
+        let start = self.runs.partition_point(|r| r.y < min.y);
+        self.runs[start..]
+            .iter()
+            .take_while(move |r| r.y <= max.y)
+            .flat_map(move |r| {
+                (r.x1.max(min.x)..r.x2.min(max.x + 1)).map(move |x| (Point { x, y: r.y }, r.count))
+            })
+    }
+
+    // bounds returns corners of the smallest box containing all covered points.
+    pub fn bounds(&self) -> Option<(Point, Point)> {
+        let first = self.runs.first()?;
+        let last = self.runs.last()?;
+        let x1 = self.runs.iter().map(|r| r.x1).min()?;
+        let x2 = self.runs.iter().map(|r| r.x2).max()?;
+        Some((
+            Point { x: x1, y: first.y },
+            Point {
+                x: x2 - 1,
+                y: last.y,
+            },
+        ))
+    }
+
+    // write_pgm writes a binary PGM heatmap of the covered area.
+    // Counts above 255 are saturated.
+    pub fn write_pgm(&self, w: &mut impl Write) -> io::Result<()> {
+        let (min, max) = match self.bounds() {
+            Some(b) => b,
+            None => return w.write_all(b"P5\n0 0\n1\n"),
+        };
+        let width = (max.x - min.x + 1) as usize;
+        let height = (max.y - min.y + 1) as usize;
+        let maxval = self
+            .runs
+            .iter()
+            .map(|r| r.count)
+            .max()
+            .unwrap_or(1)
+            .min(255);
+
+        let mut row = vec![0_u8; width];
+        let mut runs = self.runs.iter().peekable();
+        w.write_fmt(format_args!("P5\n{} {}\n{}\n", width, height, maxval))?;
+        for y in min.y..=max.y {
+            row.fill(0);
+            while let Some(r) = runs.next_if(|r| r.y == y) {
+                let x1 = (r.x1 - min.x) as usize;
+                let x2 = (r.x2 - min.x) as usize;
+                row[x1..x2].fill(r.count.min(255) as u8);
+            }
+            w.write_all(&row)?;
+        }
+        Ok(())
     }
 }
 
-fn main() -> Result<()> {
+pub fn main() -> Result<()> {
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
 
@@ -117,10 +257,16 @@ fn main() -> Result<()> {
     let amount = part2(&lines);
     io::stdout().write_fmt(format_args!("Part 2: {}\n", amount))?;
 
+    if let (Some("--heatmap"), Some(path)) = (env::args().nth(1).as_deref(), env::args().nth(2)) {
+        let mut f = BufWriter::new(File::create(path)?);
+        Coverage::new(lines.iter()).write_pgm(&mut f)?;
+        f.flush()?;
+    }
+
     Ok(())
 }
 
-fn parse(input: &str) -> Result<Vec<Line>> {
+pub fn parse(input: &str) -> Result<Vec<Line>> {
     let mut lines = Vec::new();
     for s in input.lines() {
         let (pa, pb) = s.split_once("->").ok_or("invalid line format")?;
@@ -144,59 +290,126 @@ fn parse(input: &str) -> Result<Vec<Line>> {
     Ok(lines)
 }
 
-fn part1(lines: &[Line]) -> usize {
-    let mut points = HashSet::new();
-    for (i, l1) in lines.iter().enumerate().filter(|(_, l)| !l.is_diagonal()) {
-        for (p1, r) in lines
-            .iter()
-            .skip(i + 1)
-            .filter(|l2| !l2.is_diagonal())
-            .map(|l2| l1.intersect(l2))
-            .flatten()
-        {
-            points.insert(p1);
-            if let Some(p2) = r {
-                let sx = (p2.x - p1.x).signum();
-                let sy = (p2.y - p1.y).signum();
-                let mut x = p1.x;
-                let mut y = p1.y;
-                while sx != 0 && x != p2.x || sy != 0 && y != p2.y {
-                    x += sx;
-                    y += sy;
-                    points.insert(Point { x, y });
-                }
+// count_overlaps returns the amount of points covered by at least 2 lines.
+// Rows are visited only when a line starts or ends or a sloped line crosses
+// a lattice point. Rows in between are covered by the same vertical lines only.
+pub fn count_overlaps<'a>(lines: impl Iterator<Item = &'a Line>) -> usize {
+    let mut lines: Vec<&Line> = lines.collect();
+    lines.sort_unstable_by_key(|l| l.min_y());
+
+    // Vertical lines are stored as the amount of lines for every x
+    // together with the amount of x covered at least twice.
+    let mut verticals: BTreeMap<isize, usize> = BTreeMap::new();
+    let mut vertical_overlaps = 0;
+    let mut vertical_ends: BinaryHeap<Reverse<(isize, isize)>> = BinaryHeap::new();
+    // Sloped lines are stored with the next row where they cross a lattice point.
+    let mut sloped: BinaryHeap<Reverse<(isize, usize)>> = BinaryHeap::new();
+
+    let mut spans: Vec<(isize, isize)> = Vec::new();
+    let mut events: Vec<(isize, isize)> = Vec::new();
+    let mut next = 0;
+    let mut count = 0;
+    let mut prev: Option<isize> = None;
+    loop {
+        let y = [
+            lines.get(next).map(|l| l.min_y()),
+            sloped.peek().map(|Reverse((y, _))| *y),
+            vertical_ends.peek().map(|Reverse((y, _))| *y + 1),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let y = match y {
+            Some(y) => y,
+            None => break,
+        };
+        if let Some(prev) = prev {
+            count += (y - prev - 1) as usize * vertical_overlaps;
+        }
+        prev = Some(y);
+
+        while let Some(&Reverse((end, x))) = vertical_ends.peek() {
+            if end >= y {
+                break;
+            }
+            vertical_ends.pop();
+            let c = verticals.get_mut(&x).unwrap();
+            *c -= 1;
+            vertical_overlaps -= (*c == 1) as usize;
+            if *c == 0 {
+                verticals.remove(&x);
             }
         }
-    }
 
-    points.len()
-}
+        spans.clear();
+        while next < lines.len() && lines[next].min_y() == y {
+            let l = lines[next];
+            if l.is_horizontal() {
+                spans.push((l.a.x, l.b.x));
+            } else if l.is_vertical() {
+                let c = verticals.entry(l.a.x).or_insert(0);
+                *c += 1;
+                vertical_overlaps += (*c == 2) as usize;
+                vertical_ends.push(Reverse((l.max_y(), l.a.x)));
+            } else {
+                sloped.push(Reverse((y, next)));
+            }
+            next += 1;
+        }
+        while let Some(&Reverse((row, i))) = sloped.peek() {
+            if row != y {
+                break;
+            }
+            sloped.pop();
+            let l = lines[i];
+            if let Some(span) = l.span(y) {
+                spans.push(span);
+            }
+            if y + l.step.y.abs() <= l.max_y() {
+                sloped.push(Reverse((y + l.step.y.abs(), i)));
+            }
+        }
 
-fn part2(lines: &[Line]) -> usize {
-    let mut points = HashSet::new();
-    for (i, l1) in lines.iter().enumerate() {
-        for (p1, r) in lines.iter().skip(i + 1).filter_map(|l2| l1.intersect(l2)) {
-            points.insert(p1);
-            if let Some(p2) = r {
-                let sx = (p2.x - p1.x).signum();
-                let sy = (p2.y - p1.y).signum();
-                let mut x = p1.x;
-                let mut y = p1.y;
-                while sx != 0 && x != p2.x || sy != 0 && y != p2.y {
-                    x += sx;
-                    y += sy;
-                    points.insert(Point { x, y });
-                }
+        // Points of vertical lines are counted separately,
+        // so only look at them where other lines cover the row.
+        count += vertical_overlaps;
+        events.clear();
+        for &(x1, x2) in spans.iter() {
+            events.push((x1, 1));
+            events.push((x2 + 1, -1));
+        }
+        events.sort_unstable();
+
+        let mut covered = 0;
+        let mut last = 0;
+        for &(x, d) in events.iter() {
+            if covered > 0 && x > last {
+                let range = verticals.range(last..x);
+                count += if covered >= 2 {
+                    (x - last) as usize - range.filter(|&(_, &c)| c >= 2).count()
+                } else {
+                    range.filter(|&(_, &c)| c == 1).count()
+                };
             }
+            covered += d;
+            last = x;
         }
     }
+    count
+}
+
+pub fn part1(lines: &[Line]) -> usize {
+    count_overlaps(lines.iter().filter(|l| !l.is_diagonal()))
+}
 
-    points.len()
+pub fn part2(lines: &[Line]) -> usize {
+    count_overlaps(lines.iter())
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{parse, part1, part2};
+    use crate::{parse, part1, part2, Coverage, Line, Point};
+    use std::collections::HashMap;
 
     #[test]
     fn simple() {
@@ -219,4 +432,106 @@ mod test {
         let amount = part2(&lines);
         assert_eq!(12, amount);
     }
+
+    #[test]
+    fn collinear() {
+        let input = "0,0 -> 5,0
+            8,0 -> 3,0
+            4,0 -> 4,4
+            4,2 -> 4,6
+            0,4 -> 4,0
+            6,6 -> 1,1
+            2,2 -> 3,3";
+
+        let lines = parse(input).expect("can't parse the input");
+
+        assert_eq!(6, part1(&lines));
+        assert_eq!(brute_force(&lines), part2(&lines));
+    }
+
+    #[test]
+    fn arbitrary_slope() {
+        let input = "0,0 -> 6,3
+            2,1 -> 8,4
+            0,3 -> 6,0
+            0,2 -> 6,2
+            3,0 -> 3,5
+            1,0 -> 4,9
+            0,0 -> 1,9";
+
+        let lines = parse(input).expect("can't parse the input");
+        let l = lines.iter().find(|l| l.b == Point { x: 6, y: 3 }).unwrap();
+        assert_eq!(4, points(l).count());
+        assert_eq!(brute_force(&lines), part2(&lines));
+    }
+
+    #[test]
+    fn tall() {
+        // Vertical lines overlap in rows 5 to 7 and the diagonal crosses them in row 3.
+        let input = "0,0 -> 0,1000000000
+            0,5 -> 0,7
+            -3,0 -> 3,6
+            -3,2000000000 -> 1,2000000000";
+
+        let lines = parse(input).expect("can't parse the input");
+        assert_eq!(3, part1(&lines));
+        assert_eq!(4, part2(&lines));
+    }
+
+    pub fn points(l: &Line) -> impl Iterator<Item = Point> + '_ {
+        let steps = match l.step {
+            Point { x: 0, y: 0 } => 0,
+            Point { x: 0, y } => (l.b.y - l.a.y) / y,
+            Point { x, .. } => (l.b.x - l.a.x) / x,
+        };
+        (0..=steps).map(move |i| Point {
+            x: l.a.x + i * l.step.x,
+            y: l.a.y + i * l.step.y,
+        })
+    }
+
+    fn brute_force(lines: &[Line]) -> usize {
+        let mut count = HashMap::new();
+        for p in lines.iter().flat_map(points) {
+            *count.entry(p).or_insert(0) += 1;
+        }
+        count.values().filter(|&&c| c >= 2).count()
+    }
+
+    #[test]
+    fn coverage() {
+        let input = "0,0 -> 3,0
+            1,0 -> 1,2
+            0,2 -> 2,0
+            1,1 -> 1,1";
+
+        let lines = parse(input).expect("can't parse the input");
+        let c = Coverage::new(lines.iter());
+
+        assert_eq!(7, c.count_at_least(1));
+        assert_eq!(3, c.count_at_least(2));
+        assert_eq!(1, c.count_at_least(3));
+        assert_eq!(0, c.count_at_least(4));
+        assert_eq!(3, c.get(Point { x: 1, y: 1 }));
+        assert_eq!(1, c.get(Point { x: 3, y: 0 }));
+        assert_eq!(0, c.get(Point { x: 3, y: 1 }));
+        assert_eq!(7, c.points().count());
+
+        let inside: Vec<(Point, usize)> = c
+            .in_box(Point { x: 1, y: 0 }, Point { x: 2, y: 1 })
+            .collect();
+        assert_eq!(
+            vec![
+                (Point { x: 1, y: 0 }, 2),
+                (Point { x: 2, y: 0 }, 2),
+                (Point { x: 1, y: 1 }, 3)
+            ],
+            inside
+        );
+
+        let mut pgm = Vec::new();
+        c.write_pgm(&mut pgm).unwrap();
+        assert_eq!(b"P5\n4 3\n3\n", &pgm[..9]);
+        assert_eq!(&[1, 2, 2, 1, 0, 3, 0, 0, 1, 1, 0, 0], &pgm[9..]);
+    }
 }
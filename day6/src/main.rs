@@ -1,104 +1,647 @@
+use std::env;
+use std::fmt;
 use std::io::{self, Read, Write};
 
 type Result<T> = ::std::result::Result<T, Box<dyn ::std::error::Error>>;
 
-fn main() -> Result<()> {
-    let mut input = String::new();
-    io::stdin().read_to_string(&mut input)?;
+// LifeCycle describes fish timers: after spawning the timer is set to reset
+// and a newborn fish starts with timer set to newborn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct LifeCycle {
+    reset: usize,
+    newborn: usize,
+}
 
-    let fishes = parse(&input)?;
+impl LifeCycle {
+    const LANTERNFISH: LifeCycle = LifeCycle {
+        reset: 6,
+        newborn: 8,
+    };
 
-    let amount = part1(&fishes, 80);
-    io::stdout().write_fmt(format_args!("Part 1: {}\n", amount))?;
+    fn new(reset: usize, newborn: usize) -> LifeCycle {
+        LifeCycle { reset, newborn }
+    }
 
-    let amount = part1(&fishes, 256);
-    io::stdout().write_fmt(format_args!("Part 2: {}\n", amount))?;
+    #[inline]
+    fn slots(&self) -> usize {
+        self.reset.max(self.newborn) + 1
+    }
 
-    Ok(())
+    // transition returns matrix m such that m[i][j] fish with timer i
+    // appear the next day from a single fish with timer j.
+    fn transition(&self) -> Vec<Vec<u64>> {
+        let n = self.slots();
+        let mut m = vec![vec![0; n]; n];
+        for j in 1..n {
+            m[j - 1][j] = 1;
+        }
+        m[self.reset][0] += 1;
+        m[self.newborn][0] += 1;
+        m
+    }
+
+    // next moves hist one day forward.
+    fn next<A: Arithmetic>(&self, ar: &A, hist: &mut [A::Value]) -> Result<()> {
+        hist.rotate_left(1);
+        let last = hist.len() - 1;
+        let spawning = std::mem::replace(&mut hist[last], ar.value(0)?);
+        hist[self.reset] = ar.add(&hist[self.reset], &spawning)?;
+        hist[self.newborn] = ar.add(&hist[self.newborn], &spawning)?;
+        Ok(())
+    }
 }
 
-fn parse(input: &str) -> Result<Vec<u8>> {
-    let mut fishes = Vec::new();
-    for s in input.trim().split(',') {
-        fishes.push(s.parse()?)
+// Arithmetic defines operations used by the population engine.
+trait Arithmetic {
+    type Value: Clone + fmt::Display;
+
+    fn value(&self, n: u64) -> Result<Self::Value>;
+    fn add(&self, a: &Self::Value, b: &Self::Value) -> Result<Self::Value>;
+    fn mul(&self, a: &Self::Value, b: &Self::Value) -> Result<Self::Value>;
+}
+
+// Exact uses arbitrary precision integers.
+struct Exact;
+
+impl Arithmetic for Exact {
+    type Value = BigUint;
+
+    fn value(&self, n: u64) -> Result<BigUint> {
+        Ok(BigUint::from_u64(n))
     }
-    Ok(fishes)
+
+    fn add(&self, a: &BigUint, b: &BigUint) -> Result<BigUint> {
+        Ok(a.add(b))
+    }
+
+    fn mul(&self, a: &BigUint, b: &BigUint) -> Result<BigUint> {
+        Ok(a.mul(b))
+    }
+}
+
+// Modular calculates everything modulo a prime.
+struct Modular(u64);
+
+impl Arithmetic for Modular {
+    type Value = u64;
+
+    fn value(&self, n: u64) -> Result<u64> {
+        Ok(n % self.0)
+    }
+
+    fn add(&self, a: &u64, b: &u64) -> Result<u64> {
+        Ok(((*a as u128 + *b as u128) % self.0 as u128) as u64)
+    }
+
+    fn mul(&self, a: &u64, b: &u64) -> Result<u64> {
+        Ok(((*a as u128 * *b as u128) % self.0 as u128) as u64)
+    }
+}
+
+// Checked returns an error when result doesn't fit in u128.
+struct Checked;
+
+impl Arithmetic for Checked {
+    type Value = u128;
+
+    fn value(&self, n: u64) -> Result<u128> {
+        Ok(n as u128)
+    }
+
+    fn add(&self, a: &u128, b: &u128) -> Result<u128> {
+        a.checked_add(*b).ok_or_else(|| "u128 overflow".into())
+    }
+
+    fn mul(&self, a: &u128, b: &u128) -> Result<u128> {
+        a.checked_mul(*b).ok_or_else(|| "u128 overflow".into())
+    }
+}
+
+// BigUint is an unsigned integer stored as base 2^32 digits, least significant first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct BigUint {
+    digits: Vec<u32>,
+}
+
+impl BigUint {
+    fn from_u64(n: u64) -> BigUint {
+        let mut b = BigUint {
+            digits: vec![n as u32, (n >> 32) as u32],
+        };
+        b.normalize();
+        b
+    }
+
+    fn normalize(&mut self) {
+        while let Some(0) = self.digits.last() {
+            self.digits.pop();
+        }
+    }
+
+    fn add(&self, other: &BigUint) -> BigUint {
+        let n = self.digits.len().max(other.digits.len());
+        let mut digits = Vec::with_capacity(n + 1);
+        let mut carry = 0_u64;
+        for i in 0..n {
+            let a = *self.digits.get(i).unwrap_or(&0) as u64;
+            let b = *other.digits.get(i).unwrap_or(&0) as u64;
+            let sum = a + b + carry;
+            digits.push(sum as u32);
+            carry = sum >> 32;
+        }
+        digits.push(carry as u32);
+
+        let mut b = BigUint { digits };
+        b.normalize();
+        b
+    }
+
+    fn mul(&self, other: &BigUint) -> BigUint {
+        let mut digits = vec![0_u32; self.digits.len() + other.digits.len()];
+        for (i, &a) in self.digits.iter().enumerate() {
+            let mut carry = 0_u64;
+            for (j, &b) in other.digits.iter().enumerate() {
+                let cur = digits[i + j] as u64 + a as u64 * b as u64 + carry;
+                digits[i + j] = cur as u32;
+                carry = cur >> 32;
+            }
+            digits[i + other.digits.len()] = carry as u32;
+        }
+
+        let mut b = BigUint { digits };
+        b.normalize();
+        b
+    }
+
+    // div_rem divides by a small number in place and returns the remainder.
+    fn div_rem(&mut self, d: u32) -> u32 {
+        let mut rem = 0_u64;
+        for digit in self.digits.iter_mut().rev() {
+            let cur = rem << 32 | *digit as u64;
+            *digit = (cur / d as u64) as u32;
+            rem = cur % d as u64;
+        }
+        self.normalize();
+        rem as u32
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const BASE: u32 = 1_000_000_000;
+
+        let mut n = self.clone();
+        let mut parts = Vec::new();
+        loop {
+            parts.push(n.div_rem(BASE));
+            if n.digits.is_empty() {
+                break;
+            }
+        }
+
+        let mut s = parts.pop().unwrap().to_string();
+        for p in parts.iter().rev() {
+            s.push_str(&format!("{:09}", p));
+        }
+        f.pad(&s)
+    }
+}
+
+// Matrix is a square matrix over values of some arithmetic.
+#[derive(Clone)]
+struct Matrix<T> {
+    n: usize,
+    cells: Vec<T>,
+}
+
+impl<T: Clone> Matrix<T> {
+    fn from_rows<A: Arithmetic<Value = T>>(ar: &A, rows: &[Vec<u64>]) -> Result<Matrix<T>> {
+        let mut cells = Vec::with_capacity(rows.len() * rows.len());
+        for row in rows.iter() {
+            for &c in row.iter() {
+                cells.push(ar.value(c)?);
+            }
+        }
+        Ok(Matrix {
+            n: rows.len(),
+            cells,
+        })
+    }
+
+    fn identity<A: Arithmetic<Value = T>>(ar: &A, n: usize) -> Result<Matrix<T>> {
+        let mut cells = vec![ar.value(0)?; n * n];
+        for i in 0..n {
+            cells[i * n + i] = ar.value(1)?;
+        }
+        Ok(Matrix { n, cells })
+    }
+
+    fn mul<A: Arithmetic<Value = T>>(&self, ar: &A, other: &Matrix<T>) -> Result<Matrix<T>> {
+        let n = self.n;
+        let mut cells = Vec::with_capacity(n * n);
+        for i in 0..n {
+            for j in 0..n {
+                let mut sum = ar.value(0)?;
+                for k in 0..n {
+                    let p = ar.mul(&self.cells[i * n + k], &other.cells[k * n + j])?;
+                    sum = ar.add(&sum, &p)?;
+                }
+                cells.push(sum);
+            }
+        }
+        Ok(Matrix { n, cells })
+    }
+
+    fn pow<A: Arithmetic<Value = T>>(&self, ar: &A, mut e: usize) -> Result<Matrix<T>> {
+        let mut result = Matrix::identity(ar, self.n)?;
+        let mut base = self.clone();
+        while e != 0 {
+            if e & 1 != 0 {
+                result = result.mul(ar, &base)?;
+            }
+            e >>= 1;
+            if e != 0 {
+                base = base.mul(ar, &base)?;
+            }
+        }
+        Ok(result)
+    }
+}
+
+// population calculates the sum of state after `steps` applications
+// of linear recurrence `state' = transition * state`.
+fn population<A: Arithmetic>(
+    ar: &A,
+    transition: &[Vec<u64>],
+    state: &[u64],
+    steps: usize,
+) -> Result<A::Value> {
+    let m = Matrix::from_rows(ar, transition)?.pow(ar, steps)?;
+    let mut total = ar.value(0)?;
+    for i in 0..m.n {
+        for (j, &s) in state.iter().enumerate() {
+            let p = ar.mul(&m.cells[i * m.n + j], &ar.value(s)?)?;
+            total = ar.add(&total, &p)?;
+        }
+    }
+    Ok(total)
 }
 
-fn prev(hist: &mut [usize]) {
-    // 1 at 0 result in [x0, x1, x2, x3, x4, x5, x6,    x7, x8] after n days
-    // 1 at 1 result in [x8, x0, x1, x2, x3, x4, x5, x6-x8, x7] after n days
-    //dbg!(&hist);
-    let x8 = hist[8];
-    hist.copy_within(0..=7, 1);
-    hist[0] = x8;
-    hist[7] = hist[7].saturating_sub(x8);
+// Sample is the histogram of fish timers at some day.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Sample<T> {
+    day: usize,
+    hist: Vec<T>,
+    total: T,
 }
 
-fn part1(fishes: &[u8], days: usize) -> usize {
-    let mut hist = [0_usize; 9];
+// timeline returns samples for days 0, every, 2*every, ... and for the last day.
+fn timeline<A: Arithmetic>(
+    ar: &A,
+    fishes: &[usize],
+    days: usize,
+    every: usize,
+    cycle: &LifeCycle,
+) -> Result<Vec<Sample<A::Value>>> {
+    if every == 0 {
+        return Err("sampling interval must be positive".into());
+    }
+
+    let mut hist = vec![ar.value(0)?; cycle.slots()];
     for f in fishes.iter() {
-        hist[*f as usize] += 1;
+        hist[*f] = ar.add(&hist[*f], &ar.value(1)?)?;
+    }
+
+    let mut samples = Vec::new();
+    for day in 0..=days {
+        if day % every == 0 || day == days {
+            let mut total = ar.value(0)?;
+            for h in hist.iter() {
+                total = ar.add(&total, h)?;
+            }
+            samples.push(Sample {
+                day,
+                hist: hist.clone(),
+                total,
+            });
+        }
+        if day != days {
+            cycle.next(ar, &mut hist)?;
+        }
     }
+    Ok(samples)
+}
 
-    // zero contains 1 fish at 0 day before spawning.
-    let mut zero = [0_usize; 9];
-    zero[0] = 1;
+fn write_csv<T: fmt::Display>(w: &mut impl Write, samples: &[Sample<T>]) -> io::Result<()> {
+    let slots = samples.first().map_or(0, |s| s.hist.len());
+    w.write_all(b"day")?;
+    for i in 0..slots {
+        w.write_fmt(format_args!(",t{}", i))?;
+    }
+    w.write_all(b",total\n")?;
 
-    for i in (0..=usize::BITS - days.leading_zeros()).rev() {
-        let mut tmp = [0_usize; 9];
+    for s in samples.iter() {
+        w.write_fmt(format_args!("{}", s.day))?;
+        for h in s.hist.iter() {
+            w.write_fmt(format_args!(",{}", h))?;
+        }
+        w.write_fmt(format_args!(",{}\n", s.total))?;
+    }
+    Ok(())
+}
 
-        // Calculate how much fish will spawn after 2*n days
-        // given zero = how much fish will spawn after n days.
-        let mut p = zero;
-        for i in zero.iter() {
-            for j in 0..9 {
-                tmp[j] += i * p[j];
+// first_day_above returns the first day when population exceeds threshold.
+fn first_day_above(fishes: &[usize], threshold: u128, cycle: &LifeCycle) -> Option<usize> {
+    if fishes.is_empty() {
+        return None;
+    }
+
+    let mut hist = vec![0_u128; cycle.slots()];
+    for f in fishes.iter() {
+        hist[*f] += 1;
+    }
+
+    // Population grows until it exceeds any threshold, overflow means it is already exceeded.
+    let mut day = 0;
+    loop {
+        match hist.iter().try_fold(0_u128, |acc, &h| acc.checked_add(h)) {
+            Some(total) if total <= threshold => {}
+            _ => return Some(day),
+        }
+        if cycle.next(&Checked, &mut hist).is_err() {
+            return Some(day + 1);
+        }
+        day += 1;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Exact,
+    Modulo(u64),
+    Checked,
+}
+
+struct Options {
+    cycle: LifeCycle,
+    days: Vec<usize>,
+    mode: Mode,
+    every: Option<usize>,
+    above: Option<u128>,
+}
+
+fn main() -> Result<()> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+
+    let opts = options(env::args().skip(1))?;
+    let fishes = parse(&input, &opts.cycle)?;
+
+    if opts.days.is_empty() {
+        let amount = count(&fishes, 80, &opts.cycle, opts.mode)?;
+        io::stdout().write_fmt(format_args!("Part 1: {}\n", amount))?;
+
+        let amount = count(&fishes, 256, &opts.cycle, opts.mode)?;
+        io::stdout().write_fmt(format_args!("Part 2: {}\n", amount))?;
+    }
+    for &days in opts.days.iter() {
+        let amount = count(&fishes, days, &opts.cycle, opts.mode)?;
+        io::stdout().write_fmt(format_args!("After {} days: {}\n", days, amount))?;
+    }
+
+    if let Some(threshold) = opts.above {
+        match first_day_above(&fishes, threshold, &opts.cycle) {
+            Some(day) => {
+                io::stdout().write_fmt(format_args!("Above {} on day {}\n", threshold, day))?
             }
-            prev(&mut p);
+            None => io::stdout().write_fmt(format_args!("Never above {}\n", threshold))?,
+        }
+    }
+
+    if let Some(every) = opts.every {
+        let days = opts.days.iter().copied().max().unwrap_or(256);
+        export(
+            &mut io::stdout(),
+            &fishes,
+            days,
+            every,
+            &opts.cycle,
+            opts.mode,
+        )?;
+    }
+
+    Ok(())
+}
+
+// options parses command-line flags:
+// `--reset R`, `--newborn N`, `--days D` (can be repeated), `--mod P`, `--checked`,
+// `--csv EVERY` to output the timeline and `--above T` to find when population exceeds T.
+fn options(mut args: impl Iterator<Item = String>) -> Result<Options> {
+    let mut reset = LifeCycle::LANTERNFISH.reset;
+    let mut newborn = LifeCycle::LANTERNFISH.newborn;
+    let mut days = Vec::new();
+    let mut mode = Mode::Exact;
+    let mut every = None;
+    let mut above = None;
+    while let Some(arg) = args.next() {
+        if arg == "--checked" {
+            mode = Mode::Checked;
+            continue;
         }
 
-        if days & (1 << (i as usize)) != 0 {
-            let first = tmp[0];
-            tmp.copy_within(1..=8, 0);
-            tmp[6] += first;
-            tmp[8] = first;
+        let value = args.next().ok_or("expected flag value")?;
+        match arg.as_str() {
+            "--reset" => reset = value.parse()?,
+            "--newborn" => newborn = value.parse()?,
+            "--days" => days.push(value.parse()?),
+            "--csv" => {
+                let n = value.parse()?;
+                if n == 0 {
+                    return Err("sampling interval must be positive".into());
+                }
+                every = Some(n);
+            }
+            "--above" => above = Some(value.parse()?),
+            "--mod" => {
+                let p = value.parse()?;
+                if p < 2 {
+                    return Err("modulus must be at least 2".into());
+                }
+                mode = Mode::Modulo(p);
+            }
+            _ => return Err(format!("unknown flag: {}", arg).into()),
         }
-        zero = tmp;
     }
+    Ok(Options {
+        cycle: LifeCycle::new(reset, newborn),
+        days,
+        mode,
+        every,
+        above,
+    })
+}
 
-    let mut tmp = [0_usize; 9];
-    for i in hist.iter() {
-        for j in 0..9 {
-            tmp[j] += i * zero[j];
+fn parse(input: &str, cycle: &LifeCycle) -> Result<Vec<usize>> {
+    let mut fishes = Vec::new();
+    for s in input.trim().split(',') {
+        let timer = s.parse()?;
+        if timer >= cycle.slots() {
+            return Err(format!("timer is too big: {}", timer).into());
         }
-        prev(&mut zero);
+        fishes.push(timer)
+    }
+    Ok(fishes)
+}
+
+fn part1<A: Arithmetic>(
+    fishes: &[usize],
+    days: usize,
+    cycle: &LifeCycle,
+    ar: &A,
+) -> Result<A::Value> {
+    let mut hist = vec![0_u64; cycle.slots()];
+    for f in fishes.iter() {
+        hist[*f] += 1;
     }
-    tmp.iter().sum()
+    population(ar, &cycle.transition(), &hist, days)
+}
+
+// count formats amount of fish after `days` days calculated in the specified mode.
+fn count(fishes: &[usize], days: usize, cycle: &LifeCycle, mode: Mode) -> Result<String> {
+    Ok(match mode {
+        Mode::Exact => part1(fishes, days, cycle, &Exact)?.to_string(),
+        Mode::Modulo(p) => part1(fishes, days, cycle, &Modular(p))?.to_string(),
+        Mode::Checked => part1(fishes, days, cycle, &Checked)?.to_string(),
+    })
+}
+
+// export writes the timeline as CSV calculated in the specified mode.
+fn export(
+    w: &mut impl Write,
+    fishes: &[usize],
+    days: usize,
+    every: usize,
+    cycle: &LifeCycle,
+    mode: Mode,
+) -> Result<()> {
+    match mode {
+        Mode::Exact => write_csv(w, &timeline(&Exact, fishes, days, every, cycle)?)?,
+        Mode::Modulo(p) => write_csv(w, &timeline(&Modular(p), fishes, days, every, cycle)?)?,
+        Mode::Checked => write_csv(w, &timeline(&Checked, fishes, days, every, cycle)?)?,
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{parse, part1};
+    use crate::{
+        count, export, first_day_above, options, parse, part1, timeline, Checked, Exact, LifeCycle,
+        Mode, Modular,
+    };
 
     #[test]
     fn zero_only() {
         let input = "0";
-        let lines = parse(input).expect("can't parse the input");
+        let cycle = LifeCycle::LANTERNFISH;
+        let lines = parse(input, &cycle).expect("can't parse the input");
 
-        assert_eq!(1, part1(&lines, 0));
-        assert_eq!(2, part1(&lines, 1));
-        assert_eq!(2, part1(&lines, 7));
-        assert_eq!(3, part1(&lines, 8));
-        assert_eq!(4, part1(&lines, 10));
+        assert_eq!(1, part1(&lines, 0, &cycle, &Checked).unwrap());
+        assert_eq!(2, part1(&lines, 1, &cycle, &Checked).unwrap());
+        assert_eq!(2, part1(&lines, 7, &cycle, &Checked).unwrap());
+        assert_eq!(3, part1(&lines, 8, &cycle, &Checked).unwrap());
+        assert_eq!(4, part1(&lines, 10, &cycle, &Checked).unwrap());
     }
 
     #[test]
     fn example() {
         let input = "3,4,3,1,2";
-        let lines = parse(input).expect("can't parse the input");
+        let cycle = LifeCycle::LANTERNFISH;
+        let lines = parse(input, &cycle).expect("can't parse the input");
+
+        assert_eq!(26, part1(&lines, 18, &cycle, &Checked).unwrap());
+        assert_eq!(5934, part1(&lines, 80, &cycle, &Checked).unwrap());
+    }
+
+    #[test]
+    fn life_cycle() {
+        for (reset, newborn) in [(6, 8), (2, 3), (0, 1), (4, 9), (6, 4), (3, 3), (5, 0)] {
+            let cycle = LifeCycle::new(reset, newborn);
+            let input = format!("0,{},{},1", reset, newborn);
+            let fishes = parse(&input, &cycle).expect("can't parse the input");
+
+            let mut hist = vec![0_usize; cycle.slots()];
+            for &f in fishes.iter() {
+                hist[f] += 1;
+            }
+            for days in 0..40 {
+                let amount = part1(&fishes, days, &cycle, &Checked).unwrap();
+                assert_eq!(hist.iter().sum::<usize>() as u128, amount);
+                let first = hist[0];
+                hist.rotate_left(1);
+                *hist.last_mut().unwrap() = 0;
+                hist[reset] += first;
+                hist[newborn] += first;
+            }
+        }
+
+        assert!(parse("9", &LifeCycle::LANTERNFISH).is_err());
+        assert!(parse("6", &LifeCycle::new(6, 4)).is_ok());
+    }
+
+    #[test]
+    fn modes() {
+        let cycle = LifeCycle::LANTERNFISH;
+        let lines = parse("3,4,3,1,2", &cycle).expect("can't parse the input");
+
+        let exact = part1(&lines, 256, &cycle, &Exact).unwrap();
+        assert_eq!("26984457539", exact.to_string());
+        assert_eq!(
+            26984457539 % 1_000_000_007,
+            part1(&lines, 256, &cycle, &Modular(1_000_000_007)).unwrap()
+        );
+
+        // 2^128 is about 3.4e38, so the population overflows somewhere around 3000 days.
+        assert!(part1(&lines, 4000, &cycle, &Checked).is_err());
+        let exact = part1(&lines, 4000, &cycle, &Exact).unwrap().to_string();
+        assert!(exact.len() > 39);
+
+        let p = 998_244_353;
+        let rem = exact
+            .bytes()
+            .fold(0_u64, |r, b| (r * 10 + (b - b'0') as u64) % p);
+        assert_eq!(
+            rem.to_string(),
+            count(&lines, 4000, &cycle, Mode::Modulo(p)).unwrap()
+        );
+    }
+
+    #[test]
+    fn growth_curve() {
+        let cycle = LifeCycle::LANTERNFISH;
+        let lines = parse("3,4,3,1,2", &cycle).expect("can't parse the input");
+
+        let samples = timeline(&Checked, &lines, 18, 5, &cycle).unwrap();
+        let days: Vec<usize> = samples.iter().map(|s| s.day).collect();
+        assert_eq!(vec![0, 5, 10, 15, 18], days);
+        assert_eq!(vec![0, 1, 1, 2, 1, 0, 0, 0, 0], samples[0].hist);
+        assert_eq!(26, samples[4].total);
+        assert!(timeline(&Checked, &lines, 18, 0, &cycle).is_err());
+        let args = ["--csv", "0"].iter().map(|s| s.to_string());
+        assert!(options(args).is_err());
+
+        let mut csv = Vec::new();
+        export(&mut csv, &lines, 2, 1, &cycle, Mode::Exact).unwrap();
+        assert_eq!(
+            "day,t0,t1,t2,t3,t4,t5,t6,t7,t8,total\n\
+             0,0,1,1,2,1,0,0,0,0,5\n\
+             1,1,1,2,1,0,0,0,0,0,5\n\
+             2,1,2,1,0,0,0,1,0,1,6\n",
+            String::from_utf8(csv).unwrap()
+        );
 
-        assert_eq!(26, part1(&lines, 18));
-        assert_eq!(5934, part1(&lines, 80));
+        assert_eq!(Some(0), first_day_above(&lines, 4, &cycle));
+        assert_eq!(Some(18), first_day_above(&lines, 25, &cycle));
+        assert!(first_day_above(&lines, u128::MAX, &cycle).is_some());
+        assert_eq!(None, first_day_above(&[], 0, &cycle));
     }
 }
@@ -1,24 +1,162 @@
-use std::cmp::min;
+use std::env;
 use std::io::{self, Read, Write};
 
-type Result<T> = ::std::result::Result<T, Box<dyn ::std::error::Error>>;
+pub type Result<T> = ::std::result::Result<T, Box<dyn ::std::error::Error>>;
 
-fn main() -> Result<()> {
+// CostModel defines how much fuel is needed to move a crab by some distance.
+pub trait CostModel {
+    fn cost(&self, distance: usize) -> usize;
+
+    // convex reports whether cost grows convexly with distance.
+    // Total fuel is then convex in the alignment position and can be searched for.
+    fn convex(&self) -> bool {
+        false
+    }
+
+    // optimum returns optimal position for (position, weight) pairs
+    // if it can be calculated directly.
+    fn optimum(&self, _crabs: &[(usize, usize)]) -> Option<usize> {
+        None
+    }
+
+    // separable reports whether cost of a multi-dimensional move is the sum
+    // of costs along every axis, so that axes can be solved independently.
+    fn separable(&self) -> bool {
+        false
+    }
+}
+
+pub struct Linear;
+
+impl CostModel for Linear {
+    fn cost(&self, distance: usize) -> usize {
+        distance
+    }
+
+    fn convex(&self) -> bool {
+        true
+    }
+
+    fn separable(&self) -> bool {
+        true
+    }
+
+    // Weighted median is optimal for linear cost.
+    fn optimum(&self, crabs: &[(usize, usize)]) -> Option<usize> {
+        let mut crabs = crabs.to_vec();
+        crabs.sort_unstable();
+
+        let total: usize = crabs.iter().map(|&(_, w)| w).sum();
+        let mut sum = 0;
+        for &(p, w) in crabs.iter() {
+            sum += w;
+            if 2 * sum >= total {
+                return Some(p);
+            }
+        }
+        None
+    }
+}
+
+pub struct Triangular;
+
+impl CostModel for Triangular {
+    fn cost(&self, distance: usize) -> usize {
+        sum1n(distance)
+    }
+
+    fn convex(&self) -> bool {
+        true
+    }
+}
+
+pub struct Quadratic;
+
+impl CostModel for Quadratic {
+    fn cost(&self, distance: usize) -> usize {
+        distance * distance
+    }
+
+    fn convex(&self) -> bool {
+        true
+    }
+}
+
+// Convex wraps user-defined cost function which is known to be convex.
+pub struct Convex<F>(pub F);
+
+impl<F: Fn(usize) -> usize> CostModel for Convex<F> {
+    fn cost(&self, distance: usize) -> usize {
+        (self.0)(distance)
+    }
+
+    fn convex(&self) -> bool {
+        true
+    }
+}
+
+// Any other function is treated as non-convex.
+impl<F: Fn(usize) -> usize> CostModel for F {
+    fn cost(&self, distance: usize) -> usize {
+        self(distance)
+    }
+}
+
+// Crab is a crab (or submarine) with a position in 1, 2 or 3 dimensions.
+// Fuel needed is multiplied by weight.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Crab {
+    pub position: Vec<usize>,
+    pub weight: usize,
+}
+
+pub fn main() -> Result<()> {
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
 
-    let mut positions = parse(&input)?;
+    let mut fleet = false;
+    let mut model = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--fleet" => fleet = true,
+            _ => model = Some(arg),
+        }
+    }
+
+    if fleet {
+        let crabs = parse_fleet(&input)?;
+        let (position, fuel) = match model.as_deref().unwrap_or("linear") {
+            "linear" => solve_fleet(&crabs, &Linear),
+            "triangular" => solve_fleet(&crabs, &Triangular),
+            "quadratic" => solve_fleet(&crabs, &Quadratic),
+            name => return Err(format!("unknown cost model: {}", name).into()),
+        };
+        io::stdout().write_fmt(format_args!("Position: {:?}, fuel: {}\n", position, fuel))?;
+        return Ok(());
+    }
+
+    let positions = parse(&input)?;
 
-    let number = part1(&mut positions);
+    let number = part1(&positions);
     io::stdout().write_fmt(format_args!("Part 1: {}\n", number))?;
 
     let number = part2(&positions);
     io::stdout().write_fmt(format_args!("Part 2: {}\n", number))?;
 
+    if let Some(name) = model {
+        let (position, fuel) = match name.as_str() {
+            "linear" => solve(&positions, &Linear),
+            "triangular" => solve(&positions, &Triangular),
+            "quadratic" => solve(&positions, &Quadratic),
+            _ => return Err(format!("unknown cost model: {}", name).into()),
+        };
+        io::stdout().write_fmt(format_args!("Position: {}, fuel: {}\n", position, fuel))?;
+    }
+
     Ok(())
 }
 
-fn parse(input: &str) -> Result<Vec<usize>> {
+pub fn parse(input: &str) -> Result<Vec<usize>> {
     let mut positions = Vec::new();
     for s in input.trim().split(',') {
         positions.push(s.parse()?)
@@ -26,54 +164,245 @@ fn parse(input: &str) -> Result<Vec<usize>> {
     Ok(positions)
 }
 
-fn part1(positions: &mut [usize]) -> usize {
-    positions.sort_unstable();
+// parse_fleet parses one crab per line in `x[,y[,z]] [* weight]` format.
+pub fn parse_fleet(input: &str) -> Result<Vec<Crab>> {
+    let mut crabs: Vec<Crab> = Vec::new();
+    for line in input.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let (coords, weight) = match line.split_once('*') {
+            Some((c, w)) => (c, w.trim().parse()?),
+            None => (line, 1),
+        };
 
-    let median = positions[positions.len() / 2];
-    let mut sum = 0_usize;
+        let mut position = Vec::new();
+        for s in coords.split(',') {
+            position.push(s.trim().parse()?);
+        }
+        if position.len() > 3 {
+            return Err("expected at most 3 dimensions".into());
+        }
+        if crabs
+            .first()
+            .is_some_and(|c| c.position.len() != position.len())
+        {
+            return Err("all crabs must have the same amount of dimensions".into());
+        }
+        crabs.push(Crab { position, weight });
+    }
+    Ok(crabs)
+}
+
+pub fn fuel(crabs: &[(usize, usize)], model: &impl CostModel, target: usize) -> usize {
+    crabs
+        .iter()
+        .map(|&(p, w)| w * model.cost(p.abs_diff(target)))
+        .sum()
+}
 
-    for p in positions.iter() {
-        sum += if p < &median { median - p } else { p - median };
+// solve returns optimal alignment position and total fuel needed.
+// If several positions need the same fuel, the lowest one is returned.
+pub fn solve(positions: &[usize], model: &impl CostModel) -> (usize, usize) {
+    let crabs: Vec<(usize, usize)> = positions.iter().map(|&p| (p, 1)).collect();
+    solve_weighted(&crabs, model)
+}
+
+// solve_weighted is like solve but for (position, weight) pairs.
+pub fn solve_weighted(crabs: &[(usize, usize)], model: &impl CostModel) -> (usize, usize) {
+    if let Some(position) = model.optimum(crabs) {
+        return (position, fuel(crabs, model, position));
+    }
+
+    let min = crabs.iter().map(|&(p, _)| p).min().unwrap_or(0);
+    let max = crabs.iter().map(|&(p, _)| p).max().unwrap_or(0);
+
+    if !model.convex() {
+        return (min..=max)
+            .map(|x| (x, fuel(crabs, model, x)))
+            .min_by_key(|&(_, f)| f)
+            .unwrap_or((0, 0));
     }
-    sum
+
+    // For convex fuel the difference f(x+1) - f(x) is non-decreasing,
+    // so search for the first x where it becomes non-negative.
+    let (mut lo, mut hi) = (min, max);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if fuel(crabs, model, mid) <= fuel(crabs, model, mid + 1) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    (lo, fuel(crabs, model, lo))
 }
 
-#[inline]
-fn sum1n(n: usize) -> usize {
-    n * (n + 1) / 2
+// fleet_fuel returns fuel needed for all crabs to move to target.
+// Distance is measured along the grid, so it is the sum of per-axis distances.
+pub fn fleet_fuel(crabs: &[Crab], model: &impl CostModel, target: &[usize]) -> usize {
+    crabs
+        .iter()
+        .map(|c| {
+            let distance = c
+                .position
+                .iter()
+                .zip(target)
+                .map(|(&p, &t)| p.abs_diff(t))
+                .sum();
+            c.weight * model.cost(distance)
+        })
+        .sum()
+}
+
+// solve_fleet returns optimal alignment point and total fuel needed.
+// Ties are resolved in favour of lower coordinates.
+pub fn solve_fleet(crabs: &[Crab], model: &impl CostModel) -> (Vec<usize>, usize) {
+    let dims = crabs.first().map_or(0, |c| c.position.len());
+
+    // Separable cost is minimised on every axis independently.
+    if model.separable() {
+        let mut position = Vec::with_capacity(dims);
+        for i in 0..dims {
+            let axis: Vec<(usize, usize)> =
+                crabs.iter().map(|c| (c.position[i], c.weight)).collect();
+            position.push(solve_weighted(&axis, model).0);
+        }
+        let total = fleet_fuel(crabs, model, &position);
+        return (position, total);
+    }
+
+    // Moving the target inside the bounding box of crabs never increases distances.
+    let bounds: Vec<(usize, usize)> = (0..dims)
+        .map(|i| {
+            let axis = crabs.iter().map(|c| c.position[i]);
+            (axis.clone().min().unwrap_or(0), axis.max().unwrap_or(0))
+        })
+        .collect();
+    let mut target = vec![0; dims];
+    search_fleet(crabs, model, &bounds, &mut target, 0)
 }
 
-fn part2(positions: &[usize]) -> usize {
-    let total: usize = positions.iter().sum();
-    let mean = total / positions.len();
-    let mut sum1 = 0_usize;
-    let mut sum2 = 0_usize;
+// search_fleet fixes target coordinates starting from the given axis
+// and returns the best target found with its fuel.
+fn search_fleet(
+    crabs: &[Crab],
+    model: &impl CostModel,
+    bounds: &[(usize, usize)],
+    target: &mut Vec<usize>,
+    axis: usize,
+) -> (Vec<usize>, usize) {
+    if axis == bounds.len() {
+        return (target.clone(), fleet_fuel(crabs, model, target));
+    }
+
+    let best = |x: usize, target: &mut Vec<usize>| {
+        target[axis] = x;
+        search_fleet(crabs, model, bounds, target, axis + 1)
+    };
+
+    let (min, max) = bounds[axis];
+    if !model.convex() {
+        return (min..=max)
+            .map(|x| best(x, target))
+            .min_by_key(|(_, f)| *f)
+            .unwrap_or_default();
+    }
 
-    for p in positions.iter() {
-        if *p <= mean {
-            sum1 += sum1n(mean - p);
-            sum2 += sum1n(mean + 1 - p);
+    // Convex cost of a convex distance is convex, as is its minimum over the
+    // remaining axes, so search for the first x where fuel stops decreasing.
+    let (mut lo, mut hi) = (min, max);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if best(mid, target).1 <= best(mid + 1, target).1 {
+            hi = mid;
         } else {
-            sum1 += sum1n(p - mean);
-            sum2 += sum1n(p - mean - 1);
+            lo = mid + 1;
         }
     }
-    min(sum1, sum2)
+    best(lo, target)
+}
+
+pub fn part1(positions: &[usize]) -> usize {
+    solve(positions, &Linear).1
+}
+
+#[inline]
+fn sum1n(n: usize) -> usize {
+    n * (n + 1) / 2
+}
+
+pub fn part2(positions: &[usize]) -> usize {
+    solve(positions, &Triangular).1
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{parse, part1, part2};
+    use crate::{
+        fleet_fuel, parse, parse_fleet, part1, part2, solve, solve_fleet, Convex, Crab, Linear,
+        Quadratic, Triangular,
+    };
 
     #[test]
     fn example() {
         let input = "16,1,2,0,4,2,7,1,2,14";
-        let mut positions = parse(input).expect("can't parse input");
+        let positions = parse(input).expect("can't parse input");
 
-        let number = part1(&mut positions);
+        let number = part1(&positions);
         assert_eq!(37, number);
 
         let number = part2(&positions);
         assert_eq!(168, number);
     }
+
+    #[test]
+    fn cost_models() {
+        let input = "16,1,2,0,4,2,7,1,2,14";
+        let positions = parse(input).expect("can't parse input");
+
+        assert_eq!((2, 37), solve(&positions, &Linear));
+        assert_eq!((5, 168), solve(&positions, &Triangular));
+        assert_eq!((3, 46), solve(&[0, 2, 3, 9], &Quadratic));
+        assert_eq!(
+            solve(&positions, &Triangular),
+            solve(&positions, &Convex(|d| d * (d + 1) / 2))
+        );
+
+        // Fuel is free for distances less than 3, so any position is fine for distant crabs.
+        let step = |d: usize| if d < 3 { 0 } else { 1 };
+        assert_eq!((3, 0), solve(&[1, 5], &step));
+        assert_eq!((0, 0), solve(&[], &Linear));
+    }
+
+    #[test]
+    fn fleet() {
+        let input = "1 * 3
+            10
+            4 * 2";
+        let crabs = parse_fleet(input).expect("can't parse input");
+        assert_eq!(
+            Crab {
+                position: vec![1],
+                weight: 3
+            },
+            crabs[0]
+        );
+        assert_eq!((vec![1], 9 + 6), solve_fleet(&crabs, &Linear));
+        // Weighted mean is 21/6 = 3.5, f(3) = 3*3 + 28 + 2*1 = 39 and f(4) = 3*6 + 21 + 0 = 39,
+        // so the lower position wins the tie.
+        assert_eq!((vec![3], 39), solve_fleet(&crabs, &Triangular));
+
+        let input = "0,0,5 * 2
+            4,2,5
+            6,9,1";
+        let crabs = parse_fleet(input).expect("can't parse input");
+        assert_eq!((vec![0, 0, 5], 10 + 11 + 4), solve_fleet(&crabs, &Linear));
+
+        // Crabs are 7 steps apart, so the best is to meet after 3 and 4 steps: 6 + 10.
+        // Meeting at (1, 2) needs 3 and 4 steps too, but at (1, 1) it is 2 and 5: 3 + 15.
+        let crabs = parse_fleet("0,0\n3,4").expect("can't parse input");
+        assert_eq!((vec![0, 3], 16), solve_fleet(&crabs, &Triangular));
+        assert_eq!(16, fleet_fuel(&crabs, &Triangular, &[1, 2]));
+        assert_eq!(18, fleet_fuel(&crabs, &Triangular, &[1, 1]));
+
+        assert!(parse_fleet("1,2\n3").is_err());
+        assert!(parse_fleet("1,2,3,4").is_err());
+    }
 }
@@ -1,9 +1,12 @@
 use rayon::{iter::ParallelIterator, str::ParallelString};
+use std::env;
+use std::error::Error;
+use std::fmt;
 use std::io::{self, Read, Write};
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
-fn main() -> Result<()> {
+pub fn main() -> Result<()> {
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
 
@@ -13,14 +16,27 @@ fn main() -> Result<()> {
     let count = part2(&input);
     io::stdout().write_fmt(format_args!("Part 2: {}\n", count))?;
 
+    if env::args().nth(1).as_deref() == Some("--report") {
+        for (i, r) in diagnose(&input).iter().enumerate() {
+            match r {
+                Ok(d) => io::stdout().write_fmt(format_args!(
+                    "{}: {} ({})\n",
+                    i + 1,
+                    d.output,
+                    d.wiring
+                ))?,
+                Err(e) => io::stdout().write_fmt(format_args!("{}: error: {}\n", i + 1, e))?,
+            }
+        }
+    }
+
     Ok(())
 }
 
-fn part1(input: &str) -> usize {
+pub fn part1(input: &str) -> usize {
     let mut count = 0_usize;
 
-    for line in input.lines() {
-        let (_, out) = line.split_once('|').unwrap();
+    for (_, out) in input.lines().filter_map(|line| line.split_once('|')) {
         for num in out.split_whitespace() {
             count += matches!(num.len(), 2 | 3 | 4 | 7) as usize;
         }
@@ -30,82 +46,328 @@ fn part1(input: &str) -> usize {
 }
 
 #[inline]
-fn convert(number: &str) -> u8 {
+fn convert(number: &str) -> u32 {
     number
         .as_bytes()
         .iter()
         .fold(0, |acc, b| acc | (1 << (b - b'a')))
 }
 
-pub fn part2(input: &str) -> usize {
-    input
-        .par_lines()
-        .map(|line| {
-            let (patterns, out) = line.split_once('|').unwrap();
-
-            let mut numbers = [0_u8; 10];
-
-            // num 0 1 2 3 4 5 6 7 8 9
-            // len 6 2 5 5 4 5 6 3 7 6
-            let mut i5: usize = 2;
-            let mut i6: usize = 0;
-            for pat in patterns.split_whitespace() {
-                let x = convert(pat);
-                match pat.len() {
-                    2 => numbers[1] = x,
-                    3 => numbers[7] = x,
-                    4 => numbers[4] = x,
-                    7 => numbers[8] = x,
-                    5 => {
-                        numbers[i5] = x;
-                        // i5 iterates over 2, 3, 5
-                        i5 += (i5 == 3) as usize + 1;
-                    }
-                    6 => {
-                        numbers[i6] = x;
-                        // i6 iterates over 0, 6, 9
-                        i6 += 3 * ((i6 == 0) as usize + 1);
-                    }
-                    _ => panic!("unexpected pattern"),
-                }
+// Display defines which segments are lit for every symbol.
+// Segments are numbered from 0 and named by letters starting from 'a'.
+#[derive(Debug, Clone)]
+pub struct Display {
+    segments: usize,
+    symbols: Vec<(char, u32)>,
+}
+
+// Wiring maps every wire to a display segment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wiring {
+    segments: Vec<usize>,
+}
+
+impl Wiring {
+    pub fn apply(&self, pattern: u32) -> u32 {
+        self.segments
+            .iter()
+            .enumerate()
+            .filter(|&(w, _)| pattern & (1 << w) != 0)
+            .fold(0, |acc, (_, s)| acc | (1 << s))
+    }
+}
+
+impl Display {
+    pub fn new(segments: usize, symbols: Vec<(char, u32)>) -> Result<Display> {
+        if segments > 26 {
+            return Err("too many segments".into());
+        }
+        for (i, &(c, mask)) in symbols.iter().enumerate() {
+            if mask >> segments != 0 {
+                return Err(format!("symbol {} uses unknown segment", c).into());
+            }
+            if symbols[..i].iter().any(|&(_, m)| m == mask) {
+                return Err(format!("symbol {} is not unique", c).into());
             }
+        }
+        Ok(Display { segments, symbols })
+    }
+
+    pub fn seven_segment() -> Display {
+        let digits = [
+            "abcefg", "cf", "acdeg", "acdfg", "bcdf", "abdfg", "abdefg", "acf", "abcdefg", "abcdfg",
+        ];
+        Display {
+            segments: 7,
+            symbols: ('0'..='9').zip(digits.iter().map(|d| convert(d))).collect(),
+        }
+    }
 
-            if numbers[9] & numbers[4] != numbers[4] {
-                if numbers[0] & numbers[4] != numbers[4] {
-                    numbers.swap(9, 6);
-                } else {
-                    numbers.swap(9, 0)
+    // parse reads display definition with a `<symbol> <segments>` pair on every line,
+    // e.g. `1 cf` for a seven-segment display.
+    pub fn parse(segments: usize, input: &str) -> Result<Display> {
+        let mut symbols = Vec::new();
+        for line in input.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (c, pattern) = line.split_once(' ').ok_or("invalid symbol definition")?;
+            let mut chars = c.chars();
+            let c = match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err("symbol must be a single character".into()),
+            };
+            let pattern = pattern.trim();
+            if !pattern.bytes().all(|b| b.is_ascii_lowercase()) {
+                return Err(format!("invalid segments for symbol {}", c).into());
+            }
+            symbols.push((c, convert(pattern)));
+        }
+        Display::new(segments, symbols)
+    }
+
+    pub fn decode(&self, wiring: &Wiring, pattern: u32) -> Option<char> {
+        let mask = wiring.apply(pattern);
+        self.symbols
+            .iter()
+            .find(|&&(_, m)| m == mask)
+            .map(|&(c, _)| c)
+    }
+
+    // solve finds up to `limit` wirings which map every observed pattern to some symbol.
+    pub fn solve(&self, observations: &[u32], limit: usize) -> Vec<Wiring> {
+        let full = (1 << self.segments) - 1;
+        let candidates = observations
+            .iter()
+            .map(|&p| {
+                self.symbols
+                    .iter()
+                    .map(|&(_, m)| m)
+                    .filter(|m| m.count_ones() == p.count_ones())
+                    .collect()
+            })
+            .collect();
+        let state = State {
+            allowed: vec![full; self.segments],
+            candidates,
+        };
+
+        let mut result = Vec::new();
+        self.search(observations, state, limit, &mut result);
+        result
+    }
+
+    fn search(
+        &self,
+        observations: &[u32],
+        mut state: State,
+        limit: usize,
+        result: &mut Vec<Wiring>,
+    ) {
+        if result.len() >= limit || !state.propagate(observations, self.segments) {
+            return;
+        }
+
+        let branch = (0..self.segments)
+            .filter(|&w| state.allowed[w].count_ones() > 1)
+            .min_by_key(|&w| state.allowed[w].count_ones());
+        let w = match branch {
+            Some(w) => w,
+            None => {
+                let wiring = Wiring {
+                    segments: state
+                        .allowed
+                        .iter()
+                        .map(|a| a.trailing_zeros() as usize)
+                        .collect(),
+                };
+                if observations
+                    .iter()
+                    .all(|&p| self.decode(&wiring, p).is_some())
+                {
+                    result.push(wiring);
                 }
+                return;
             }
+        };
+
+        let mut options = state.allowed[w];
+        while options != 0 {
+            let s = options & options.wrapping_neg();
+            options ^= s;
+
+            let mut next = state.clone();
+            next.allowed[w] = s;
+            self.search(observations, next, limit, result);
+        }
+    }
+}
+
+#[derive(Clone)]
+struct State {
+    // Segments every wire can be connected to.
+    allowed: Vec<u32>,
+    // Symbols every observed pattern can represent.
+    candidates: Vec<Vec<u32>>,
+}
+
+impl State {
+    // propagate narrows down allowed segments until nothing changes.
+    // It returns false if constraints are contradictory.
+    fn propagate(&mut self, observations: &[u32], segments: usize) -> bool {
+        let full: u32 = (1 << segments) - 1;
+        loop {
+            let before = self.allowed.clone();
 
-            if numbers[0] & numbers[1] != numbers[1] {
-                numbers.swap(0, 6);
+            for (&p, cands) in observations.iter().zip(self.candidates.iter_mut()) {
+                let allowed = &self.allowed;
+                cands.retain(|&m| {
+                    (0..segments).all(|w| {
+                        let inside = if p & (1 << w) != 0 { m } else { full & !m };
+                        allowed[w] & inside != 0
+                    })
+                });
+                if cands.is_empty() {
+                    return false;
+                }
+
+                for w in 0..segments {
+                    let mut possible = 0;
+                    for &m in cands.iter() {
+                        possible |= if p & (1 << w) != 0 { m } else { full & !m };
+                    }
+                    self.allowed[w] &= possible;
+                }
             }
 
-            if numbers[3] & numbers[7] != numbers[7] {
-                if numbers[2] & numbers[7] != numbers[7] {
-                    numbers.swap(3, 5);
-                } else {
-                    numbers.swap(3, 2);
+            // Every segment is connected to exactly one wire.
+            for w in 0..segments {
+                let a = self.allowed[w];
+                if a == 0 {
+                    return false;
+                }
+                if a.count_ones() == 1 {
+                    for (v, other) in self.allowed.iter_mut().enumerate() {
+                        if v != w {
+                            *other &= !a;
+                        }
+                    }
                 }
             }
 
-            if numbers[5] & numbers[6] != numbers[5] {
-                numbers.swap(2, 5);
+            if self.allowed == before {
+                return self.allowed.iter().all(|&a| a != 0);
             }
+        }
+    }
+}
 
-            out.split_whitespace()
-                .map(|s| convert(s))
-                .fold(0, |acc, d| {
-                    (acc * 10) + numbers.iter().position(|&n| n == d).unwrap()
-                })
-        })
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    Format(String),
+    Contradictory,
+    Ambiguous,
+    UnknownOutput(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Format(s) => write!(f, "invalid format: {}", s),
+            DecodeError::Contradictory => write!(f, "patterns are contradictory"),
+            DecodeError::Ambiguous => write!(f, "patterns are ambiguous"),
+            DecodeError::UnknownOutput(s) => write!(f, "unknown output pattern: {}", s),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+impl fmt::Display for Wiring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (w, &s) in self.segments.iter().enumerate() {
+            if w != 0 {
+                f.write_str(" ")?;
+            }
+            write!(
+                f,
+                "{}:{}",
+                (b'a' + w as u8) as char,
+                (b'a' + s as u8) as char
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Decoded is a successfully decoded display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoded {
+    pub wiring: Wiring,
+    pub output: String,
+}
+
+impl Decoded {
+    pub fn value(&self) -> Option<usize> {
+        self.output.parse().ok()
+    }
+}
+
+// decode_line recovers wiring from patterns in `<patterns> | <output>` line
+// and decodes the output.
+pub fn decode_line(display: &Display, line: &str) -> std::result::Result<Decoded, DecodeError> {
+    let (patterns, out) = line
+        .split_once('|')
+        .ok_or_else(|| DecodeError::Format("missing '|'".to_string()))?;
+
+    let parse = |s: &str| -> std::result::Result<u32, DecodeError> {
+        let last = b'a' + display.segments as u8;
+        if s.bytes().all(|b| (b'a'..last).contains(&b)) {
+            Ok(convert(s))
+        } else {
+            Err(DecodeError::Format(format!("invalid pattern {}", s)))
+        }
+    };
+
+    let patterns = patterns
+        .split_whitespace()
+        .map(parse)
+        .collect::<std::result::Result<Vec<u32>, _>>()?;
+    let mut solutions = display.solve(&patterns, 2);
+    let wiring = match (solutions.pop(), solutions.is_empty()) {
+        (Some(w), true) => w,
+        (Some(_), false) => return Err(DecodeError::Ambiguous),
+        (None, _) => return Err(DecodeError::Contradictory),
+    };
+
+    let mut output = String::new();
+    for s in out.split_whitespace() {
+        match display.decode(&wiring, parse(s)?) {
+            Some(c) => output.push(c),
+            None => return Err(DecodeError::UnknownOutput(s.to_string())),
+        }
+    }
+    Ok(Decoded { wiring, output })
+}
+
+// diagnose decodes every line of the input independently.
+pub fn diagnose(input: &str) -> Vec<std::result::Result<Decoded, DecodeError>> {
+    let display = Display::seven_segment();
+    input
+        .par_lines()
+        .map(|line| decode_line(&display, line))
+        .collect()
+}
+
+// part2 sums outputs of all displays which could be decoded.
+pub fn part2(input: &str) -> usize {
+    let display = Display::seven_segment();
+    input
+        .par_lines()
+        .filter_map(|line| decode_line(&display, line).ok()?.value())
         .sum::<usize>()
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{part1, part2};
+    use crate::{convert, decode_line, diagnose, part1, part2, DecodeError, Display};
 
     #[test]
     fn example() {
@@ -124,4 +386,88 @@ mod tests {
         assert_eq!(26, part1(input));
         assert_eq!(61229, part2(input));
     }
+
+    #[test]
+    fn fourteen_segment() {
+        // Segments a-f are outer, g/h are middle, i-k are upper and l-n are lower inner segments.
+        let display = Display::parse(
+            14,
+            "0 abcdefjm
+            1 bcj
+            2 abdegh
+            3 abcdgh
+            4 bcfgh
+            5 acdfgh
+            6 acdefgh
+            7 abc
+            8 abcdefgh
+            9 abcdfgh
+            A abcefgh
+            B abcdhjm
+            C adef
+            D abcdjm
+            E adefg
+            F aefg
+            K efgjl
+            M bcefik
+            N bcefil
+            X ikln",
+        )
+        .unwrap();
+
+        // Wire w is connected to segment (w * 5 + 3) % 14.
+        let scramble = |s: &str| {
+            s.bytes().fold(0, |acc, b| {
+                let w = (0..14)
+                    .find(|w| (w * 5 + 3) % 14 == (b - b'a') as u32)
+                    .unwrap();
+                acc | (1 << w)
+            })
+        };
+        let patterns = [
+            "abcdefjm", "bcj", "abdegh", "abcdgh", "bcfgh", "acdfgh", "acdefgh", "abc", "abcdefgh",
+            "abcdfgh", "abcefgh", "abcdhjm", "adef", "abcdjm", "adefg", "aefg", "efgjl", "bcefik",
+            "bcefil", "ikln",
+        ];
+        let observed: Vec<u32> = patterns.iter().map(|p| scramble(p)).collect();
+
+        let solutions = display.solve(&observed, 2);
+        assert_eq!(1, solutions.len());
+        for (w, &s) in solutions[0].segments.iter().enumerate() {
+            assert_eq!((w * 5 + 3) % 14, s);
+        }
+        assert_eq!(Some('K'), display.decode(&solutions[0], scramble("efgjl")));
+        assert_eq!(None, display.decode(&solutions[0], convert("a")));
+
+        assert!(Display::parse(7, "1 cf\n7 cf").is_err());
+        assert!(Display::parse(7, "1 ch").is_err());
+    }
+
+    #[test]
+    fn diagnostics() {
+        let input =
+            "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf
+            acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab
+            acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb abc | cdfeb
+            acedgfb cdfbe gcdfa fbcad dab | cdfeb
+            acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb af
+            acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb xy";
+
+        let results = diagnose(input);
+        let decoded = results[0].as_ref().unwrap();
+        assert_eq!(Some(5353), decoded.value());
+        assert_eq!("a:c b:f c:g d:a e:b f:d g:e", decoded.wiring.to_string());
+
+        assert!(matches!(results[1], Err(DecodeError::Format(_))));
+        assert_eq!(Err(DecodeError::Contradictory), results[2]);
+        assert_eq!(Err(DecodeError::Ambiguous), results[3]);
+        assert_eq!(
+            Err(DecodeError::UnknownOutput("af".to_string())),
+            results[4]
+        );
+        assert!(matches!(results[5], Err(DecodeError::Format(_))));
+
+        assert_eq!(5353, part2(input));
+        assert!(decode_line(&Display::seven_segment(), "ab | ab").is_err());
+    }
 }
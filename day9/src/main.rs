@@ -1,19 +1,56 @@
-use std::collections::binary_heap::BinaryHeap;
-use std::io::{self, Read, Write};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::env;
+use std::io::{self, BufRead, Read, Write};
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+pub fn main() -> Result<()> {
+    let mut config = LowPointConfig::default();
+    let mut map = false;
+    let mut stream = false;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--plateaus" => config.plateaus = true,
+            "--eight" => config.connectivity = Connectivity::Eight,
+            "--risk-map" => map = true,
+            "--stream" => stream = true,
+            _ => return Err(format!("unknown flag: {}", arg).into()),
+        }
+    }
+
+    if stream {
+        let basins = BasinConfig {
+            connectivity: config.connectivity,
+            ..BasinConfig::default()
+        };
+        let top = stream_top_basins(io::stdin().lock(), &basins)?;
+        io::stdout().write_fmt(format_args!("Part 2: {}\n", top.iter().product::<usize>()))?;
+        return Ok(());
+    }
 
-fn main() -> Result<()> {
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
 
     io::stdout().write_fmt(format_args!("Part 1: {}\n", part1(&input)))?;
     io::stdout().write_fmt(format_args!("Part 2: {}\n", part2(&input)))?;
 
+    if map {
+        let (width, heights) = parse(&input);
+        let lows = low_points(width, &heights, &config);
+        io::stdout().write_fmt(format_args!(
+            "Risk: {}\n",
+            lows.iter().map(|l| l.risk()).sum::<usize>()
+        ))?;
+        io::stdout().write_all(
+            risk_map(width, heights.len().checked_div(width).unwrap_or(0), &lows).as_bytes(),
+        )?;
+    }
+
     Ok(())
 }
 
-fn parse(input: &str) -> (usize, Vec<u8>) {
+pub fn parse(input: &str) -> (usize, Vec<u8>) {
     let mut width = 0;
     let mut heights = Vec::new();
 
@@ -25,7 +62,7 @@ fn parse(input: &str) -> (usize, Vec<u8>) {
     (width, heights)
 }
 
-fn part1(input: &str) -> usize {
+pub fn part1(input: &str) -> usize {
     let (width, heights) = parse(input);
     let mut sum = 0_usize;
     let mut i = 0_usize;
@@ -49,83 +86,455 @@ fn part1(input: &str) -> usize {
     sum
 }
 
-fn merge(aliases: &mut [usize], a: usize, b: usize) {
-    let mut x = aliases[a];
-    let mut y = aliases[b];
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    Four,
+    Eight,
+}
 
-    while aliases[x] != x || aliases[y] != y {
-        debug_assert!(aliases[x] <= x);
-        debug_assert!(aliases[y] <= y);
-        x = aliases[x];
-        y = aliases[y];
+impl Connectivity {
+    // neighbours returns indices of cells adjacent to i in a grid of given width and height.
+    pub fn neighbours(self, i: usize, width: usize, height: usize) -> impl Iterator<Item = usize> {
+        const OFFSETS: [(isize, isize); 8] = [
+            (0, -1),
+            (-1, 0),
+            (1, 0),
+            (0, 1),
+            (-1, -1),
+            (1, -1),
+            (-1, 1),
+            (1, 1),
+        ];
+        let n = match self {
+            Connectivity::Four => 4,
+            Connectivity::Eight => 8,
+        };
+        let (x, y) = ((i % width) as isize, (i / width) as isize);
+        OFFSETS[..n].iter().filter_map(move |&(dx, dy)| {
+            let (nx, ny) = (x + dx, y + dy);
+            (nx >= 0 && ny >= 0 && (nx as usize) < width && (ny as usize) < height)
+                .then(|| ny as usize * width + nx as usize)
+        })
     }
+}
 
-    let min = x.min(y);
-    aliases[x] = min;
-    aliases[y] = min;
-    aliases[a] = min;
-    aliases[b] = min;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LowPointConfig {
+    pub connectivity: Connectivity,
+    // Whether regions of equal height can be low points.
+    pub plateaus: bool,
 }
 
-pub fn part2(input: &str) -> usize {
+impl Default for LowPointConfig {
+    fn default() -> Self {
+        LowPointConfig {
+            connectivity: Connectivity::Four,
+            plateaus: false,
+        }
+    }
+}
+
+// LowPoint is a region of cells with equal height which are lower than all their neighbours.
+// Without plateaus every region contains a single cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LowPoint {
+    pub position: (usize, usize),
+    pub height: u8,
+    pub cells: Vec<(usize, usize)>,
+}
+
+impl LowPoint {
+    #[inline]
+    pub fn risk(&self) -> usize {
+        1 + self.height as usize
+    }
+}
+
+pub fn low_points(width: usize, heights: &[u8], config: &LowPointConfig) -> Vec<LowPoint> {
+    let height = heights.len().checked_div(width).unwrap_or(0);
+    let mut seen = vec![false; heights.len()];
+    let mut lows = Vec::new();
+    let mut stack = Vec::new();
+
+    for start in 0..heights.len() {
+        if seen[start] {
+            continue;
+        }
+
+        // Find the region of equal height and check that all its neighbours are higher.
+        let h = heights[start];
+        let mut cells = Vec::new();
+        let mut lowest = true;
+        seen[start] = true;
+        stack.push(start);
+        while let Some(i) = stack.pop() {
+            cells.push((i % width, i / width));
+            for j in config.connectivity.neighbours(i, width, height) {
+                if heights[j] < h {
+                    lowest = false;
+                } else if heights[j] == h && !seen[j] {
+                    seen[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+
+        if lowest && (config.plateaus || cells.len() == 1) {
+            cells.sort_unstable_by_key(|&(x, y)| (y, x));
+            lows.push(LowPoint {
+                position: cells[0],
+                height: h,
+                cells,
+            });
+        }
+    }
+    lows
+}
+
+// risk_map returns a map where cells of low points contain their risk level.
+pub fn risk_map(width: usize, height: usize, lows: &[LowPoint]) -> String {
+    let mut map = vec![vec!['.'; width]; height];
+    for l in lows.iter() {
+        let c = char::from_digit(l.risk() as u32, 36).unwrap_or('#');
+        for &(x, y) in l.cells.iter() {
+            map[y][x] = c;
+        }
+    }
+
+    let mut s = String::with_capacity((width + 1) * height);
+    for row in map.iter() {
+        s.extend(row.iter());
+        s.push('\n');
+    }
+    s
+}
+
+pub fn part1_with(input: &str, config: &LowPointConfig) -> usize {
     let (width, heights) = parse(input);
+    low_points(width, &heights, config)
+        .iter()
+        .map(|l| l.risk())
+        .sum()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BasinConfig {
+    // Cells with height greater or equal to barrier don't belong to any basin.
+    pub barrier: u8,
+    pub connectivity: Connectivity,
+    // Amount of the biggest basins to multiply in part 2.
+    pub top: usize,
+}
+
+impl Default for BasinConfig {
+    fn default() -> Self {
+        BasinConfig {
+            barrier: 9,
+            connectivity: Connectivity::Four,
+            top: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Basins {
+    pub width: usize,
+    // Basin of every cell, None for barriers.
+    pub labels: Vec<Option<usize>>,
+    pub sizes: Vec<usize>,
+    // Index of the lowest cell in every basin.
+    pub low_points: Vec<usize>,
+}
+
+impl Basins {
+    // top returns basin numbers sorted by size in descending order, at most n of them.
+    pub fn top(&self, n: usize) -> Vec<usize> {
+        let mut basins: Vec<usize> = (0..self.sizes.len()).collect();
+        basins.sort_by_key(|&b| Reverse(self.sizes[b]));
+        basins.truncate(n);
+        basins
+    }
+}
+
+pub fn basins(width: usize, heights: &[u8], config: &BasinConfig) -> Basins {
+    let height = heights.len().checked_div(width).unwrap_or(0);
+    let mut labels = vec![None; heights.len()];
+    let mut sizes = Vec::new();
+    let mut low_points = Vec::new();
+    let mut stack = Vec::new();
+
+    for start in 0..heights.len() {
+        if labels[start].is_some() || heights[start] >= config.barrier {
+            continue;
+        }
+
+        let label = sizes.len();
+        let mut size = 0;
+        let mut low = start;
+        labels[start] = Some(label);
+        stack.push(start);
+        while let Some(i) = stack.pop() {
+            size += 1;
+            if heights[i] < heights[low] || heights[i] == heights[low] && i < low {
+                low = i;
+            }
+            for j in config.connectivity.neighbours(i, width, height) {
+                if labels[j].is_none() && heights[j] < config.barrier {
+                    labels[j] = Some(label);
+                    stack.push(j);
+                }
+            }
+        }
+        sizes.push(size);
+        low_points.push(low);
+    }
 
-    let mut line = vec![usize::MAX; width];
-    let mut aliases = Vec::<usize>::new();
+    Basins {
+        width,
+        labels,
+        sizes,
+        low_points,
+    }
+}
+
+const NONE: usize = usize::MAX;
+
+fn find(parents: &mut [usize], mut x: usize) -> usize {
+    while parents[x] != x {
+        parents[x] = parents[parents[x]];
+        x = parents[x];
+    }
+    x
+}
+
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let a = find(parents, a);
+    let b = find(parents, b);
+    parents[a.max(b)] = a.min(b);
+}
+
+// stream_basins reads height map row by row and calls `finished` with the size
+// of every basin as soon as it can't grow anymore.
+// Only labels of the last row are kept in memory.
+pub fn stream_basins<R: BufRead>(
+    mut reader: R,
+    config: &BasinConfig,
+    mut finished: impl FnMut(usize),
+) -> Result<()> {
+    let diagonal = config.connectivity == Connectivity::Eight;
+    let mut buf = String::new();
+    let mut width = None;
+
+    // Labels of the previous row and sizes of its basins.
+    let mut prev = Vec::<usize>::new();
     let mut sizes = Vec::<usize>::new();
-    aliases.push(0);
-
-    for row in heights.chunks(width) {
-        let mut current_size = 0;
-        for (&r, cell) in row.iter().zip(line.iter_mut()) {
-            if r != 9 {
-                current_size += 1;
-                if *cell != usize::MAX && aliases[*cell] != *aliases.last().unwrap() {
-                    let l = aliases.len() - 1;
-                    merge(&mut aliases, l, *cell);
+    let mut cur = Vec::<usize>::new();
+    let mut parents = Vec::<usize>::new();
+    let mut totals = Vec::<usize>::new();
+    let mut relabel = Vec::<usize>::new();
+
+    loop {
+        buf.clear();
+        let eof = reader.read_line(&mut buf)? == 0;
+        let row = buf.trim().as_bytes();
+        if !eof && row.is_empty() {
+            continue;
+        }
+        if !eof {
+            match width {
+                None => {
+                    width = Some(row.len());
+                    prev = vec![NONE; row.len()];
                 }
-                *cell = aliases.len() - 1;
+                Some(w) if w != row.len() => return Err("rows have different lengths".into()),
+                _ => {}
+            }
+            if !row.iter().all(u8::is_ascii_digit) {
+                return Err("expected digits".into());
+            }
+        }
+
+        // Basins of the previous row are 0..sizes.len(), runs in the current row follow them.
+        parents.clear();
+        parents.extend(0..sizes.len());
+        totals.clear();
+        totals.extend_from_slice(&sizes);
+        cur.clear();
+        for (x, &c) in row.iter().enumerate() {
+            if c - b'0' >= config.barrier {
+                cur.push(NONE);
+                continue;
+            }
+            if x == 0 || cur[x - 1] == NONE {
+                parents.push(parents.len());
+                totals.push(0);
+            }
+            let run = parents.len() - 1;
+            cur.push(run);
+            totals[run] += 1;
+
+            let (lo, hi) = if diagonal {
+                (x.saturating_sub(1), (x + 1).min(row.len() - 1))
             } else {
-                *cell = usize::MAX;
-                if current_size != 0 {
-                    sizes.push(current_size);
-                    aliases.push(aliases.len());
-                    current_size = 0;
-                }
+                (x, x)
+            };
+            for &p in prev[lo..=hi].iter().filter(|&&p| p != NONE) {
+                union(&mut parents, p, run);
+            }
+        }
+
+        // Sum sizes into roots and assign new labels to basins continuing in this row.
+        for i in 0..parents.len() {
+            let r = find(&mut parents, i);
+            if r != i {
+                totals[r] += totals[i];
+            }
+        }
+        relabel.clear();
+        relabel.resize(parents.len(), NONE);
+        sizes.clear();
+        for c in cur.iter_mut().filter(|c| **c != NONE) {
+            let r = find(&mut parents, *c);
+            if relabel[r] == NONE {
+                relabel[r] = sizes.len();
+                sizes.push(totals[r]);
             }
+            *c = relabel[r];
         }
-        if current_size != 0 {
-            sizes.push(current_size);
-            aliases.push(aliases.len());
+        for i in 0..parents.len() {
+            if parents[i] == i && relabel[i] == NONE {
+                finished(totals[i]);
+            }
         }
+
+        if eof {
+            return Ok(());
+        }
+        std::mem::swap(&mut prev, &mut cur);
     }
+}
 
+// stream_top_basins returns sizes of the `config.top` biggest basins in descending order.
+pub fn stream_top_basins<R: BufRead>(reader: R, config: &BasinConfig) -> Result<Vec<usize>> {
     let mut heap = BinaryHeap::new();
-    for i in (1..aliases.len() - 1).rev() {
-        if aliases[i] != i {
-            sizes[aliases[i]] += sizes[i];
-        } else {
-            let size = sizes[i];
-            if heap.len() < 3 {
-                heap.push(std::cmp::Reverse(size));
-            } else if heap.peek().unwrap().0 < size {
-                *heap.peek_mut().unwrap() = std::cmp::Reverse(size);
+    stream_basins(reader, config, |size| {
+        if heap.len() < config.top {
+            heap.push(Reverse(size));
+        } else if let Some(mut min) = heap.peek_mut() {
+            if min.0 < size {
+                *min = Reverse(size);
             }
         }
-    }
+    })?;
+    Ok(heap.into_sorted_vec().into_iter().map(|r| r.0).collect())
+}
 
-    heap.iter().map(|t| t.0).product()
+pub fn part2(input: &str) -> usize {
+    part2_with(input, &BasinConfig::default())
+}
+
+pub fn part2_with(input: &str, config: &BasinConfig) -> usize {
+    let (width, heights) = parse(input);
+    let b = basins(width, &heights, config);
+    b.top(config.top).iter().map(|&i| b.sizes[i]).product()
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{part1, part2};
+    use crate::{
+        basins, low_points, parse, part1, part1_with, part2, part2_with, risk_map, stream_basins,
+        stream_top_basins, BasinConfig, Connectivity, LowPointConfig,
+    };
 
     #[test]
     fn example() {
         let input = "2199943210\n3987894921\n9856789892\n8767896789\n9899965678";
 
-        assert_eq!(15, part1(&input));
-        assert_eq!(1134, part2(&input));
+        assert_eq!(15, part1(input));
+        assert_eq!(1134, part2(input));
+    }
+
+    #[test]
+    fn labelling() {
+        let input = "2199943210\n3987894921\n9856789892\n8767896789\n9899965678";
+        let (width, heights) = parse(input);
+
+        let b = basins(width, &heights, &BasinConfig::default());
+        assert_eq!(vec![3, 9, 14, 9], b.sizes);
+        assert_eq!(vec![1, 9, 22, 46], b.low_points);
+        assert_eq!(Some(0), b.labels[0]);
+        assert_eq!(None, b.labels[2]);
+        assert_eq!(vec![2, 1], b.top(2));
+
+        let config = BasinConfig {
+            barrier: 8,
+            connectivity: Connectivity::Four,
+            top: 2,
+        };
+        let b = basins(width, &heights, &config);
+        assert_eq!(vec![3, 9, 7, 6], b.sizes);
+        assert_eq!(9 * 7, part2_with(input, &config));
+
+        let config = BasinConfig {
+            connectivity: Connectivity::Eight,
+            top: 1,
+            ..BasinConfig::default()
+        };
+        assert_eq!(3 + 9 + 14 + 9, part2_with(input, &config));
+    }
+
+    #[test]
+    fn plateaus() {
+        let input = "2199943210\n3987894921\n9856789892\n8767896789\n9899965678";
+        assert_eq!(15, part1_with(input, &LowPointConfig::default()));
+
+        let input = "99999\n91199\n99929\n55999";
+        let (width, heights) = parse(input);
+        // Only the single cell with height 2 is a strict low point.
+        assert_eq!(3, part1_with(input, &LowPointConfig::default()));
+
+        let config = LowPointConfig {
+            plateaus: true,
+            ..LowPointConfig::default()
+        };
+        let lows = low_points(width, &heights, &config);
+        let positions: Vec<(usize, usize)> = lows.iter().map(|l| l.position).collect();
+        assert_eq!(vec![(1, 1), (3, 2), (0, 3)], positions);
+        assert_eq!(vec![(1, 1), (2, 1)], lows[0].cells);
+        assert_eq!(".....\n.22..\n...3.\n66...\n", risk_map(width, 4, &lows));
+
+        let config = LowPointConfig {
+            connectivity: Connectivity::Eight,
+            plateaus: true,
+        };
+        assert_eq!(2 + 6, part1_with(input, &config));
+    }
+
+    #[test]
+    fn streaming() {
+        let input = "2199943210\n3987894921\n9856789892\n8767896789\n9899965678\n";
+        let config = BasinConfig::default();
+        assert_eq!(
+            vec![14, 9, 9],
+            stream_top_basins(input.as_bytes(), &config).unwrap()
+        );
+
+        let input = "1919191\n9191919\n1199911\n9919199\n9111919";
+        let (width, heights) = parse(input);
+        for connectivity in [Connectivity::Four, Connectivity::Eight] {
+            let config = BasinConfig {
+                connectivity,
+                ..BasinConfig::default()
+            };
+            let mut expected = basins(width, &heights, &config).sizes;
+            expected.sort_unstable();
+
+            let mut sizes = Vec::new();
+            stream_basins(input.as_bytes(), &config, |s| sizes.push(s)).unwrap();
+            sizes.sort_unstable();
+            assert_eq!(expected, sizes);
+        }
+
+        assert!(stream_top_basins("123\n12".as_bytes(), &BasinConfig::default()).is_err());
     }
 }